@@ -103,7 +103,9 @@ pub async fn handle_configure() -> Result<(), Box<dyn Error>> {
                             style("goose configure").cyan()
                         );
                     }
-                    Some(ConfigError::KeyringError(msg)) => {
+                    Some(
+                        ConfigError::KeyringError(msg) | ConfigError::SecretBackendUnavailable(msg),
+                    ) => {
                         #[cfg(target_os = "macos")]
                         println!(
                             "\n  {} Failed to access secure storage (keyring): {} \n  Please check your system keychain and run '{}' again. \n  If your system is unable to use the keyring, please try setting secret key(s) via environment variables.",
@@ -128,11 +130,16 @@ pub async fn handle_configure() -> Result<(), Box<dyn Error>> {
                             style("goose configure").cyan()
                         );
                     }
-                    Some(ConfigError::DeserializeError(msg)) => {
+                    Some(ConfigError::DeserializeError { key, message, .. }) => {
+                        let field = key
+                            .as_deref()
+                            .map(|key| format!(" for '{}'", key))
+                            .unwrap_or_default();
                         println!(
-                            "\n  {} Invalid configuration value: {} \n  Please check your input and run '{}' again",
+                            "\n  {} Invalid configuration value{}: {} \n  Please check your input and run '{}' again",
                             style("Error").red().italic(),
-                            msg,
+                            field,
+                            message,
                             style("goose configure").cyan()
                         );
                     }
@@ -191,7 +191,9 @@ pub async fn get_extensions(
             // Return UNPROCESSABLE_ENTITY only for DeserializeError, INTERNAL_SERVER_ERROR for everything else
             if err
                 .downcast_ref::<goose::config::base::ConfigError>()
-                .is_some_and(|e| matches!(e, goose::config::base::ConfigError::DeserializeError(_)))
+                .is_some_and(|e| {
+                    matches!(e, goose::config::base::ConfigError::DeserializeError { .. })
+                })
             {
                 Err(StatusCode::UNPROCESSABLE_ENTITY)
             } else {
@@ -2,14 +2,22 @@ use etcetera::{choose_app_strategy, AppStrategy, AppStrategyArgs};
 use fs2::FileExt;
 use keyring::Entry;
 use once_cell::sync::{Lazy, OnceCell};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
-use std::fs::OpenOptions;
-use std::io::Write;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::time::{Duration, Instant, SystemTime};
 use thiserror::Error;
+use zeroize::Zeroizing;
+
+use super::crypto;
+use super::watch::{self, ConfigChange, WatchHandle, DEFAULT_WATCH_DEBOUNCE};
 
 pub static APP_STRATEGY: Lazy<AppStrategyArgs> = Lazy::new(|| AppStrategyArgs {
     top_level_domain: "Block".to_string(),
@@ -17,43 +25,178 @@ pub static APP_STRATEGY: Lazy<AppStrategyArgs> = Lazy::new(|| AppStrategyArgs {
     app_name: "goose".to_string(),
 });
 
-const KEYRING_SERVICE: &str = "goose";
+// Reserved secret key holding the values replaced by rotate_secret, by secret key
+const PREVIOUS_SECRETS_KEY: &str = "__previous_secrets";
 const KEYRING_USERNAME: &str = "secrets";
+// When set, the file secret store is encrypted with a key derived from it
+const SECRETS_PASSPHRASE_ENV: &str = "GOOSE_SECRETS_PASSPHRASE";
+// When set, an unusable keyring is an error instead of falling back to a file
+const KEYRING_STRICT_ENV: &str = "GOOSE_KEYRING_STRICT";
+// Shown in place of secret values that must not be exposed
+const REDACTED: &str = "***";
+// Reserved key holding the schema version of the stored config
+const CONFIG_VERSION_KEY: &str = "__config_version";
+// The schema version written when no migrations are registered. Files without
+// a version key predate versioning and count as version 0.
+const CONFIG_VERSION: u32 = 1;
+
+/// Upgrades config values from one schema version to a later one.
+pub type Migration = Box<dyn Fn(&mut HashMap<String, Value>) + Send + Sync>;
 
 #[cfg(test)]
 const TEST_KEYRING_SERVICE: &str = "goose-test";
+// Read by Config::load_dotenv when no path is given, relative to the working directory
+const DOTENV_FILE: &str = ".env";
+
+/// Why a value couldn't be deserialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeserializeKind {
+    /// The file or text isn't valid YAML, JSON or TOML
+    Syntax,
+    /// The value is well formed but has the wrong shape for the requested
+    /// type, e.g. a string where a number is expected
+    TypeMismatch,
+}
 
 #[derive(Error, Debug)]
 pub enum ConfigError {
     #[error("Configuration value not found: {0}")]
     NotFound(String),
-    #[error("Failed to deserialize value: {0}")]
-    DeserializeError(String),
+    #[error("{}", describe_deserialize_error(.key.as_deref(), .message))]
+    DeserializeError {
+        /// The config key whose value failed, if the failure was for a single key
+        key: Option<String>,
+        kind: DeserializeKind,
+        message: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
     #[error("Failed to read config file: {0}")]
     FileError(#[from] std::io::Error),
     #[error("Failed to create config directory: {0}")]
     DirectoryError(String),
     #[error("Failed to access keyring: {0}")]
-    KeyringError(String),
+    KeyringError(#[source] keyring::Error),
+    #[error("Secret storage is unavailable: {0}")]
+    SecretBackendUnavailable(#[source] keyring::Error),
     #[error("Failed to lock config file: {0}")]
     LockError(String),
+    #[error("Failed to watch config file: {0}")]
+    WatchError(#[from] notify::Error),
+    #[error("Secrets are read from environment variables only and cannot be modified")]
+    EnvOnlyReadOnly,
+    #[error("Failed to decrypt secrets file: {0}")]
+    SecretsDecryptionError(String),
+    #[error("Failed to encrypt secrets file: {0}")]
+    SecretsEncryptionError(String),
+}
+
+fn describe_deserialize_error(key: Option<&str>, message: &str) -> String {
+    match key {
+        Some(key) => format!("Failed to deserialize value of `{}`: {}", key, message),
+        None => format!("Failed to deserialize value: {}", message),
+    }
+}
+
+impl ConfigError {
+    fn deserialize(
+        kind: DeserializeKind,
+        source: impl std::error::Error + Send + Sync + 'static,
+    ) -> Self {
+        ConfigError::DeserializeError {
+            key: None,
+            kind,
+            message: source.to_string(),
+            source: Box::new(source),
+        }
+    }
+
+    /// The config key whose value couldn't be deserialized, so tooling can
+    /// point at the offending field. None for other errors, and for files that
+    /// couldn't be parsed at all.
+    pub fn failed_key(&self) -> Option<&str> {
+        match self {
+            ConfigError::DeserializeError { key, .. } => key.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Why a value couldn't be deserialized, for [`ConfigError::DeserializeError`]
+    pub fn deserialize_kind(&self) -> Option<DeserializeKind> {
+        match self {
+            ConfigError::DeserializeError { kind, .. } => Some(*kind),
+            _ => None,
+        }
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(err: toml::de::Error) -> Self {
+        ConfigError::deserialize(DeserializeKind::Syntax, err)
+    }
 }
 
 impl From<serde_json::Error> for ConfigError {
     fn from(err: serde_json::Error) -> Self {
-        ConfigError::DeserializeError(err.to_string())
+        let kind = match err.classify() {
+            serde_json::error::Category::Data => DeserializeKind::TypeMismatch,
+            _ => DeserializeKind::Syntax,
+        };
+        ConfigError::deserialize(kind, err)
     }
 }
 
 impl From<serde_yaml::Error> for ConfigError {
     fn from(err: serde_yaml::Error) -> Self {
-        ConfigError::DeserializeError(err.to_string())
+        ConfigError::deserialize(DeserializeKind::Syntax, err)
+    }
+}
+
+impl From<dotenv::Error> for ConfigError {
+    fn from(err: dotenv::Error) -> Self {
+        match err {
+            dotenv::Error::Io(err) => ConfigError::FileError(err),
+            err => ConfigError::deserialize(DeserializeKind::Syntax, err),
+        }
     }
 }
 
 impl From<keyring::Error> for ConfigError {
     fn from(err: keyring::Error) -> Self {
-        ConfigError::KeyringError(err.to_string())
+        match err {
+            // The keyring itself couldn't be reached, as opposed to a problem with an entry
+            keyring::Error::PlatformFailure(_) | keyring::Error::NoStorageAccess(_) => {
+                ConfigError::SecretBackendUnavailable(err)
+            }
+            _ => ConfigError::KeyringError(err),
+        }
+    }
+}
+
+/// Access to a keyring that stores passwords by service and username.
+///
+/// The system keyring is used by default; alternative implementations can be
+/// supplied with [`Config::new_with_keyring_backend`].
+pub trait KeyringBackend: Send + Sync {
+    fn get_password(&self, service: &str, username: &str) -> keyring::Result<String>;
+    fn set_password(&self, service: &str, username: &str, password: &str) -> keyring::Result<()>;
+    fn delete_password(&self, service: &str, username: &str) -> keyring::Result<()>;
+}
+
+/// The platform keyring (macOS Keychain, Windows Credential Manager, Secret Service).
+pub struct SystemKeyring;
+
+impl KeyringBackend for SystemKeyring {
+    fn get_password(&self, service: &str, username: &str) -> keyring::Result<String> {
+        Entry::new(service, username)?.get_password()
+    }
+
+    fn set_password(&self, service: &str, username: &str, password: &str) -> keyring::Result<()> {
+        Entry::new(service, username)?.set_password(password)
+    }
+
+    fn delete_password(&self, service: &str, username: &str) -> keyring::Result<()> {
+        Entry::new(service, username)?.delete_credential()
     }
 }
 
@@ -77,6 +220,10 @@ impl From<keyring::Error> for ConfigError {
 /// 3. If the keyring is disabled, secrets are stored in a secrets file
 ///    (~/.config/goose/secrets.yaml by default)
 ///
+/// When the system keyring can't be reached at all (e.g. on headless servers),
+/// the default config falls back to the secrets file with a warning. Set
+/// GOOSE_KEYRING_STRICT to fail instead.
+///
 /// # Examples
 ///
 /// ```no_run
@@ -104,13 +251,116 @@ impl From<keyring::Error> for ConfigError {
 ///
 /// For Goose-specific configuration, consider prefixing with "goose_" to avoid conflicts.
 pub struct Config {
-    config_path: PathBuf,
+    storage: ConfigStorage,
     secrets: SecretStorage,
+    secret_keys: RwLock<HashSet<String>>,
+    // Serializes read-modify-write cycles within this process
+    write_lock: Mutex<()>,
+    // Keyed by the version each migration upgrades from, with the version it
+    // upgrades to
+    migrations: RwLock<BTreeMap<u32, (u32, Migration)>>,
+    // Compiled-in fallbacks used when a key is neither in the env nor stored
+    defaults: HashMap<String, Value>,
+    // Values read by get_param_cached, with the time they were read
+    param_cache: Mutex<HashMap<String, (Instant, Value)>>,
+    // The last parsed contents of the config file, shared with watchers so
+    // they can invalidate it
+    file_cache: Arc<Mutex<Option<FileCache>>>,
+    // Set once the keyring has failed and secrets moved to the fallback file
+    keyring_fallen_back: AtomicBool,
+    // Overrides loaded by load_dotenv, keyed by uppercase name
+    dotenv: RwLock<HashMap<String, String>>,
+}
+
+// Parsed file contents, valid while the file's mtime and size are unchanged
+struct FileCache {
+    modified: SystemTime,
+    len: u64,
+    values: HashMap<String, Value>,
+}
+
+enum ConfigStorage {
+    File {
+        path: PathBuf,
+    },
+    Memory {
+        values: Mutex<HashMap<String, Value>>,
+    },
 }
 
 enum SecretStorage {
-    Keyring { service: String },
-    File { path: PathBuf },
+    Keyring {
+        service: String,
+        username: String,
+        backend: Arc<dyn KeyringBackend>,
+        // Secrets file used instead if the keyring turns out to be unusable
+        fallback: Option<PathBuf>,
+    },
+    File {
+        path: PathBuf,
+    },
+    // Values are kept serialized in buffers that are zeroed when dropped, so
+    // secrets don't linger in freed memory once replaced or removed. Copies
+    // handed out by load_secrets and get_secret are ordinary values.
+    Memory {
+        values: Mutex<HashMap<String, Zeroizing<String>>>,
+    },
+    // Secrets only come from environment variables and are never persisted
+    EnvOnly,
+}
+
+/// Where a [`Config`] actually reads and writes its secrets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretBackend {
+    /// The system keyring, or the backend given to [`Config::new_with_keyring_backend`]
+    Keyring,
+    /// A secrets file, either configured or used because the keyring is unavailable
+    File(PathBuf),
+    /// Process memory, for in-memory configs
+    Memory,
+    /// Environment variables only, see [`Config::new_with_env_only_secrets`]
+    Env,
+}
+
+/// Where the value returned by [`Config::get_param`] came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueSource {
+    /// An environment variable, or an override loaded with [`Config::load_dotenv`]
+    Env,
+    /// The user's config file (or in-memory store)
+    File,
+    /// The defaults embedded with [`Config::with_defaults`]
+    Default,
+}
+
+/// The serialization format of configuration read with [`Config::from_reader`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Yaml,
+    Json,
+    Toml,
+}
+
+/// Options for [`Config::export`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExportOptions {
+    /// Include secret values instead of redacting them to `"***"`
+    pub include_secrets: bool,
+}
+
+// The document written by Config::export and read by Config::import
+#[derive(Serialize, Deserialize)]
+struct ConfigSnapshot {
+    #[serde(default)]
+    params: BTreeMap<String, Value>,
+    #[serde(default)]
+    secrets: BTreeMap<String, Value>,
+}
+
+// Held for the duration of a read-modify-write cycle on the config
+struct ConfigLock<'a> {
+    _guard: MutexGuard<'a, ()>,
+    _file: Option<File>,
 }
 
 // Global instance
@@ -118,39 +368,55 @@ static GLOBAL_CONFIG: OnceCell<Config> = OnceCell::new();
 
 impl Default for Config {
     fn default() -> Self {
+        Config::with_app_strategy(APP_STRATEGY.clone()).expect("goose requires a home dir")
+    }
+}
+
+impl Config {
+    /// Get the global configuration instance.
+    ///
+    /// This will initialize the configuration with the default path (~/.config/goose/config.yaml)
+    /// if it hasn't been initialized yet.
+    pub fn global() -> &'static Config {
+        GLOBAL_CONFIG.get_or_init(Config::default)
+    }
+
+    /// Create a configuration in the config directory of the given app
+    ///
+    /// This resolves paths the same way as the default configuration, but with
+    /// the caller's vendor and app name, so forks and white-label builds keep
+    /// their config apart from goose's. The app name also becomes the keyring
+    /// service.
+    pub fn with_app_strategy(args: AppStrategyArgs) -> Result<Self, ConfigError> {
         // choose_app_strategy().config_dir()
         // - macOS/Linux: ~/.config/goose/
         // - Windows:     ~\AppData\Roaming\Block\goose\config\
-        let config_dir = choose_app_strategy(APP_STRATEGY.clone())
-            .expect("goose requires a home dir")
+        let service = args.app_name.clone();
+        let config_dir = choose_app_strategy(args)
+            .map_err(|e| ConfigError::DirectoryError(e.to_string()))?
             .config_dir();
 
-        std::fs::create_dir_all(&config_dir).expect("Failed to create config directory");
+        std::fs::create_dir_all(&config_dir)
+            .map_err(|e| ConfigError::DirectoryError(e.to_string()))?;
 
         let config_path = config_dir.join("config.yaml");
 
+        let secrets_path = config_dir.join("secrets.yaml");
         let secrets = match env::var("GOOSE_DISABLE_KEYRING") {
-            Ok(_) => SecretStorage::File {
-                path: config_dir.join("secrets.yaml"),
-            },
+            Ok(_) => SecretStorage::File { path: secrets_path },
             Err(_) => SecretStorage::Keyring {
-                service: KEYRING_SERVICE.to_string(),
+                service,
+                username: KEYRING_USERNAME.to_string(),
+                backend: Arc::new(SystemKeyring),
+                fallback: env::var(KEYRING_STRICT_ENV)
+                    .is_err()
+                    .then_some(secrets_path),
             },
         };
-        Config {
-            config_path,
+        Ok(Config::with_storage(
+            ConfigStorage::File { path: config_path },
             secrets,
-        }
-    }
-}
-
-impl Config {
-    /// Get the global configuration instance.
-    ///
-    /// This will initialize the configuration with the default path (~/.config/goose/config.yaml)
-    /// if it hasn't been initialized yet.
-    pub fn global() -> &'static Config {
-        GLOBAL_CONFIG.get_or_init(Config::default)
+        ))
     }
 
     /// Create a new configuration instance with custom paths
@@ -158,651 +424,4068 @@ impl Config {
     /// This is primarily useful for testing or for applications that need
     /// to manage multiple configuration files.
     pub fn new<P: AsRef<Path>>(config_path: P, service: &str) -> Result<Self, ConfigError> {
-        Ok(Config {
-            config_path: config_path.as_ref().to_path_buf(),
-            secrets: SecretStorage::Keyring {
+        Self::new_with_keyring_backend(config_path, service, Arc::new(SystemKeyring))
+    }
+
+    /// Create a new configuration instance that stores secrets in the given keyring
+    ///
+    /// This is primarily useful for testing, or for applications that keep
+    /// secrets in a keyring other than the platform one.
+    pub fn new_with_keyring_backend<P: AsRef<Path>>(
+        config_path: P,
+        service: &str,
+        backend: Arc<dyn KeyringBackend>,
+    ) -> Result<Self, ConfigError> {
+        Ok(Config::with_storage(
+            ConfigStorage::File {
+                path: config_path.as_ref().to_path_buf(),
+            },
+            SecretStorage::Keyring {
                 service: service.to_string(),
+                username: KEYRING_USERNAME.to_string(),
+                backend,
+                fallback: None,
             },
-        })
+        ))
     }
 
     /// Create a new configuration instance with custom paths
     ///
     /// This is primarily useful for testing or for applications that need
     /// to manage multiple configuration files.
+    ///
+    /// Secrets are written as plaintext YAML unless `GOOSE_SECRETS_PASSPHRASE`
+    /// is set, in which case they're encrypted with a key derived from it.
+    /// Plaintext secrets files can still be read and are encrypted on the next
+    /// write.
     pub fn new_with_file_secrets<P1: AsRef<Path>, P2: AsRef<Path>>(
         config_path: P1,
         secrets_path: P2,
     ) -> Result<Self, ConfigError> {
-        Ok(Config {
-            config_path: config_path.as_ref().to_path_buf(),
-            secrets: SecretStorage::File {
+        Ok(Config::with_storage(
+            ConfigStorage::File {
+                path: config_path.as_ref().to_path_buf(),
+            },
+            SecretStorage::File {
                 path: secrets_path.as_ref().to_path_buf(),
             },
-        })
+        ))
     }
 
-    /// Check if this config already exists
-    pub fn exists(&self) -> bool {
-        self.config_path.exists()
+    /// Create a new configuration instance whose secrets only come from environment variables
+    ///
+    /// [`Config::get_secret`] reads environment variables as usual, but there is
+    /// no secret store behind them: [`Config::set_secret`] and
+    /// [`Config::delete_secret`] fail with [`ConfigError::EnvOnlyReadOnly`], so
+    /// no credential ever lands on disk or in a keyring. This is intended for
+    /// ephemeral environments such as CI.
+    pub fn new_with_env_only_secrets<P: AsRef<Path>>(config_path: P) -> Self {
+        Config::with_storage(
+            ConfigStorage::File {
+                path: config_path.as_ref().to_path_buf(),
+            },
+            SecretStorage::EnvOnly,
+        )
     }
 
-    /// Check if this config already exists
-    pub fn clear(&self) -> Result<(), ConfigError> {
-        Ok(std::fs::remove_file(&self.config_path)?)
+    /// Create a new configuration instance that keeps params and secrets in memory
+    ///
+    /// Nothing is written to disk or the keyring. This is useful for testing and
+    /// for building up a configuration (e.g. during onboarding) that is later
+    /// persisted with [`Config::clone_to`].
+    pub fn new_in_memory() -> Self {
+        Config::with_storage(
+            ConfigStorage::Memory {
+                values: Mutex::new(HashMap::new()),
+            },
+            SecretStorage::Memory {
+                values: Mutex::new(HashMap::new()),
+            },
+        )
     }
 
-    /// Get the path to the configuration file
-    pub fn path(&self) -> String {
-        self.config_path.to_string_lossy().to_string()
-    }
+    /// Create an in-memory configuration from any reader, such as stdin or an HTTP body
+    ///
+    /// The content is parsed in the given format and becomes the initial params
+    /// of a [`Config::new_in_memory`] config, so later writes stay in memory.
+    /// Use [`Config::clone_to`] to persist the result to a file-backed config.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if the reader fails or the content can't be parsed
+    pub fn from_reader<R: Read>(mut reader: R, format: ConfigFormat) -> Result<Self, ConfigError> {
+        let mut content = String::new();
+        reader.read_to_string(&mut content)?;
+        let values = parse_values(&content, format)?;
 
-    // Load current values from the config file
-    pub fn load_values(&self) -> Result<HashMap<String, Value>, ConfigError> {
-        if self.config_path.exists() {
-            let file_content = std::fs::read_to_string(&self.config_path)?;
-            // Parse YAML into JSON Value for consistent internal representation
-            let yaml_value: serde_yaml::Value = serde_yaml::from_str(&file_content)?;
-            let json_value: Value = serde_json::to_value(yaml_value)?;
-
-            match json_value {
-                Value::Object(map) => Ok(map.into_iter().collect()),
-                _ => Ok(HashMap::new()),
-            }
-        } else {
-            Ok(HashMap::new())
-        }
+        Ok(Config::with_storage(
+            ConfigStorage::Memory {
+                values: Mutex::new(values),
+            },
+            SecretStorage::Memory {
+                values: Mutex::new(HashMap::new()),
+            },
+        ))
     }
 
-    // Save current values to the config file
-    pub fn save_values(&self, values: HashMap<String, Value>) -> Result<(), ConfigError> {
-        // Convert to YAML for storage
-        let yaml_value = serde_yaml::to_string(&values)?;
+    /// Serve the given values when a key is neither in the environment nor stored
+    ///
+    /// Defaults are never written to the config file, so the user's file only
+    /// holds the values they changed, and [`Config::get_param_source`] reports
+    /// [`ValueSource::Default`] for values served from here.
+    pub fn with_defaults(mut self, defaults: HashMap<String, Value>) -> Self {
+        self.defaults = defaults;
+        self
+    }
 
-        // Ensure the directory exists
-        if let Some(parent) = self.config_path.parent() {
-            std::fs::create_dir_all(parent)
-                .map_err(|e| ConfigError::DirectoryError(e.to_string()))?;
+    /// Store keyring secrets under the given account instead of the default `secrets`
+    ///
+    /// Secrets live in a single keyring entry identified by service and account,
+    /// so distinct accounts keep separate secrets within the same service. This
+    /// allows per-profile or per-app isolation. It has no effect when secrets
+    /// are not stored in a keyring.
+    pub fn with_keyring_account(mut self, account: &str) -> Self {
+        if let SecretStorage::Keyring { username, .. } = &mut self.secrets {
+            *username = account.to_string();
         }
-
-        // Open the file with write permissions, create if it doesn't exist
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&self.config_path)?;
-
-        // Acquire an exclusive lock
-        file.lock_exclusive()
-            .map_err(|e| ConfigError::LockError(e.to_string()))?;
-
-        // Write the contents using the same file handle
-        file.write_all(yaml_value.as_bytes())?;
-        file.sync_all()?;
-
-        // Unlock is handled automatically when file is dropped
-        Ok(())
+        self
     }
 
-    // Load current secrets from the keyring
-    pub fn load_secrets(&self) -> Result<HashMap<String, Value>, ConfigError> {
-        match &self.secrets {
-            SecretStorage::Keyring { service } => {
-                let entry = Entry::new(service, KEYRING_USERNAME)?;
+    /// Store secrets in the given file if the keyring turns out to be unusable
+    ///
+    /// The keyring is still tried first. If it can't be reached at all, a
+    /// warning is logged once and secrets are read from and written to `path`
+    /// from then on. Secrets already in the keyring are not copied over. It has
+    /// no effect when secrets are not stored in a keyring.
+    pub fn with_keyring_fallback<P: AsRef<Path>>(mut self, path: P) -> Self {
+        if let SecretStorage::Keyring { fallback, .. } = &mut self.secrets {
+            *fallback = Some(path.as_ref().to_path_buf());
+        }
+        self
+    }
 
-                match entry.get_password() {
-                    Ok(content) => {
-                        let values: HashMap<String, Value> = serde_json::from_str(&content)?;
-                        Ok(values)
-                    }
-                    Err(keyring::Error::NoEntry) => Ok(HashMap::new()),
-                    Err(e) => Err(ConfigError::KeyringError(e.to_string())),
-                }
-            }
-            SecretStorage::File { path } => {
-                if path.exists() {
-                    let file_content = std::fs::read_to_string(path)?;
-                    let yaml_value: serde_yaml::Value = serde_yaml::from_str(&file_content)?;
-                    let json_value: Value = serde_json::to_value(yaml_value)?;
-                    match json_value {
-                        Value::Object(map) => Ok(map.into_iter().collect()),
-                        _ => Ok(HashMap::new()),
-                    }
-                } else {
-                    Ok(HashMap::new())
-                }
-            }
+    fn with_storage(storage: ConfigStorage, secrets: SecretStorage) -> Self {
+        Config {
+            storage,
+            secrets,
+            secret_keys: RwLock::new(HashSet::new()),
+            write_lock: Mutex::new(()),
+            migrations: RwLock::new(BTreeMap::new()),
+            defaults: HashMap::new(),
+            param_cache: Mutex::new(HashMap::new()),
+            file_cache: Arc::new(Mutex::new(None)),
+            keyring_fallen_back: AtomicBool::new(false),
+            dotenv: RwLock::new(HashMap::new()),
         }
     }
 
-    // check all possible places for a parameter
-    pub fn get(&self, key: &str, is_secret: bool) -> Result<Value, ConfigError> {
-        if is_secret {
-            self.get_secret(key)
-        } else {
-            self.get_param(key)
+    /// Check if this config already exists
+    pub fn exists(&self) -> bool {
+        match &self.storage {
+            ConfigStorage::File { path } => path.exists(),
+            ConfigStorage::Memory { .. } => true,
         }
     }
 
-    // save a parameter in the appropriate location based on if it's secret or not
-    pub fn set(&self, key: &str, value: Value, is_secret: bool) -> Result<(), ConfigError> {
-        if is_secret {
-            self.set_secret(key, value)
-        } else {
-            self.set_param(key, value)
+    /// Check if this config already exists
+    pub fn clear(&self) -> Result<(), ConfigError> {
+        match &self.storage {
+            ConfigStorage::File { path } => Ok(std::fs::remove_file(path)?),
+            ConfigStorage::Memory { values } => {
+                values.lock().expect("config values lock poisoned").clear();
+                Ok(())
+            }
         }
     }
 
-    /// Get a configuration value (non-secret).
-    ///
-    /// This will attempt to get the value from:
-    /// 1. Environment variable with the exact key name
-    /// 2. Configuration file
+    /// Create an empty config file if it doesn't exist yet
     ///
-    /// The value will be deserialized into the requested type. This works with
-    /// both simple types (String, i32, etc.) and complex types that implement
-    /// serde::Deserialize.
+    /// The parent directory is created as needed. On Unix the new file is only
+    /// readable and writable by the current user. An existing file is left
+    /// untouched, and in-memory configs are unaffected.
     ///
     /// # Errors
     ///
-    /// Returns a ConfigError if:
-    /// - The key doesn't exist in either environment or config file
-    /// - The value cannot be deserialized into the requested type
-    /// - There is an error reading the config file
-    pub fn get_param<T: for<'de> Deserialize<'de>>(&self, key: &str) -> Result<T, ConfigError> {
-        // First check environment variables (convert to uppercase)
-        let env_key = key.to_uppercase();
-        if let Ok(val) = env::var(&env_key) {
-            // Parse the environment variable value into a serde_json::Value
-            let value: Value = serde_json::from_str(&val).unwrap_or(Value::String(val));
-            return Ok(serde_json::from_value(value)?);
+    /// Returns a ConfigError if the directory or file can't be created
+    pub fn touch(&self) -> Result<(), ConfigError> {
+        let path = match &self.storage {
+            ConfigStorage::File { path } => path,
+            ConfigStorage::Memory { .. } => return Ok(()),
+        };
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| ConfigError::DirectoryError(e.to_string()))?;
         }
 
-        // Load current values from file
-        let values = self.load_values()?;
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
 
-        // Then check our stored values
-        values
-            .get(key)
-            .ok_or_else(|| ConfigError::NotFound(key.to_string()))
-            .and_then(|v| Ok(serde_json::from_value(v.clone())?))
+        // Creating the file fails if it exists, so there's no race with another writer
+        match options.open(path) {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Ok(()),
+            Err(e) => Err(e.into()),
+        }
     }
 
-    /// Set a configuration value in the config file (non-secret).
-    ///
-    /// This will immediately write the value to the config file. The value
-    /// can be any type that can be serialized to JSON/YAML.
-    ///
-    /// Note that this does not affect environment variables - those can only
-    /// be set through the system environment.
+    /// Get the path to the configuration file
     ///
-    /// # Errors
+    /// Returns `<in-memory>` for configs created with [`Config::new_in_memory`].
+    pub fn path(&self) -> String {
+        match &self.storage {
+            ConfigStorage::File { path } => path.to_string_lossy().to_string(),
+            ConfigStorage::Memory { .. } => "<in-memory>".to_string(),
+        }
+    }
+
+    /// Get the path to the configuration file, or `None` for in-memory configs
     ///
-    /// Returns a ConfigError if:
-    /// - There is an error reading or writing the config file
-    /// - There is an error serializing the value
-    pub fn set_param(&self, key: &str, value: Value) -> Result<(), ConfigError> {
-        let mut values = self.load_values()?;
-        values.insert(key.to_string(), value);
+    /// Unlike [`Config::path`], this can't be confused with a file that happens
+    /// to be named like the in-memory placeholder, so prefer it when opening or
+    /// watching the file.
+    pub fn path_buf(&self) -> Option<PathBuf> {
+        match &self.storage {
+            ConfigStorage::File { path } => Some(path.clone()),
+            ConfigStorage::Memory { .. } => None,
+        }
+    }
 
-        self.save_values(values)
+    /// Count the top-level keys stored in the config.
+    ///
+    /// Only stored values are counted; values supplied solely through
+    /// environment variables can't be enumerated and are not included.
+    pub fn len(&self) -> Result<usize, ConfigError> {
+        Ok(self.load_values()?.len())
     }
 
-    /// Delete a configuration value in the config file.
+    /// Check whether the config has no stored keys.
     ///
-    /// This will immediately write the value to the config file. The value
-    /// can be any type that can be serialized to JSON/YAML.
+    /// Like [`Config::len`], this ignores values set only in the environment.
+    pub fn is_empty(&self) -> Result<bool, ConfigError> {
+        Ok(self.load_values()?.is_empty())
+    }
+
+    /// List the names of all stored config params, sorted.
     ///
-    /// Note that this does not affect environment variables - those can only
-    /// be set through the system environment.
+    /// Like [`Config::len`], this only covers stored values. Use
+    /// [`Config::list_params_with_sources`] to see which of them are
+    /// currently overridden by the environment.
+    pub fn list_params(&self) -> Result<Vec<String>, ConfigError> {
+        let mut keys: Vec<String> = self.load_values()?.into_keys().collect();
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// List stored and default params, sorted, with where each one's value
+    /// currently comes from.
     ///
-    /// # Errors
+    /// A key reported as [`ValueSource::Env`] is shadowed by an environment
+    /// variable, as it would be for [`Config::get_param`].
+    pub fn list_params_with_sources(&self) -> Result<Vec<(String, ValueSource)>, ConfigError> {
+        let stored = self.load_values()?;
+        let keys: BTreeMap<&String, ValueSource> = self
+            .defaults
+            .keys()
+            .map(|key| (key, ValueSource::Default))
+            .chain(stored.keys().map(|key| (key, ValueSource::File)))
+            .collect();
+        Ok(keys
+            .into_iter()
+            .map(|(key, source)| match self.env_override(key) {
+                Some(_) => (key.clone(), ValueSource::Env),
+                None => (key.clone(), source),
+            })
+            .collect())
+    }
+
+    /// List the names of all stored secrets, sorted, without their values.
     ///
-    /// Returns a ConfigError if:
-    /// - There is an error reading or writing the config file
-    /// - There is an error serializing the value
-    pub fn delete(&self, key: &str) -> Result<(), ConfigError> {
-        let mut values = self.load_values()?;
-        values.remove(key);
+    /// Previous values kept by [`Config::rotate_secret`] are not listed.
+    pub fn list_secret_keys(&self) -> Result<Vec<String>, ConfigError> {
+        let mut keys: Vec<String> = self.load_secrets()?.into_keys().collect();
+        keys.sort();
+        Ok(keys)
+    }
 
-        self.save_values(values)
+    /// List the names of all stored secrets, sorted, with whether each one is
+    /// currently overridden by an environment variable ([`ValueSource::Env`])
+    /// or read from the secret store ([`ValueSource::File`]).
+    pub fn list_secret_keys_with_sources(&self) -> Result<Vec<(String, ValueSource)>, ConfigError> {
+        Ok(self
+            .list_secret_keys()?
+            .into_iter()
+            .map(|key| match self.env_override(&key) {
+                Some(_) => (key, ValueSource::Env),
+                None => (key, ValueSource::File),
+            })
+            .collect())
     }
 
-    /// Get a secret value.
-    ///
-    /// This will attempt to get the value from:
-    /// 1. Environment variable with the exact key name
-    /// 2. System keyring
-    ///
-    /// The value will be deserialized into the requested type. This works with
-    /// both simple types (String, i32, etc.) and complex types that implement
-    /// serde::Deserialize.
-    ///
-    /// # Errors
-    ///
-    /// Returns a ConfigError if:
-    /// - The key doesn't exist in either environment or keyring
-    /// - The value cannot be deserialized into the requested type
-    /// - There is an error accessing the keyring
-    pub fn get_secret<T: for<'de> Deserialize<'de>>(&self, key: &str) -> Result<T, ConfigError> {
-        // First check environment variables (convert to uppercase)
-        let env_key = key.to_uppercase();
-        if let Ok(val) = env::var(&env_key) {
-            let value: Value = serde_json::from_str(&val).unwrap_or(Value::String(val));
-            return Ok(serde_json::from_value(value)?);
+    // Load current values from the config file, migrating them if they were
+    // written with an older schema version
+    pub fn load_values(&self) -> Result<HashMap<String, Value>, ConfigError> {
+        let mut values = self.read_values()?;
+        if !self.migrate_values(&mut values) {
+            return Ok(values);
         }
 
-        // Then check keyring
-        let values = self.load_secrets()?;
-        values
-            .get(key)
-            .ok_or_else(|| ConfigError::NotFound(key.to_string()))
-            .and_then(|v| Ok(serde_json::from_value(v.clone())?))
+        // Writing the migration back is a read-modify-write cycle, so it's
+        // redone under the lock on the latest contents
+        let lock = self.lock()?;
+        self.load_values_locked(&lock)
     }
 
-    /// Set a secret value in the system keyring.
-    ///
-    /// This will store the value in a single JSON object in the system keyring,
-    /// alongside any other secrets. The value can be any type that can be
-    /// serialized to JSON.
+    // Like load_values, for read-modify-write cycles already holding the lock
+    fn load_values_locked(
+        &self,
+        _lock: &ConfigLock<'_>,
+    ) -> Result<HashMap<String, Value>, ConfigError> {
+        let mut values = self.read_values()?;
+        if self.migrate_values(&mut values) {
+            self.save_values(values.clone())?;
+        }
+        Ok(values)
+    }
+
+    // The stored values as they are, including the version key
+    fn read_values(&self) -> Result<HashMap<String, Value>, ConfigError> {
+        match &self.storage {
+            ConfigStorage::File { path } => self.read_file_values(path),
+            ConfigStorage::Memory { values } => {
+                Ok(values.lock().expect("config values lock poisoned").clone())
+            }
+        }
+    }
+
+    // Read the config file, reusing the last parsed values if the file's
+    // mtime and size haven't changed since
+    fn read_file_values(&self, path: &Path) -> Result<HashMap<String, Value>, ConfigError> {
+        let mut cache = self.file_cache.lock().expect("file cache lock poisoned");
+        let metadata = match std::fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                *cache = None;
+                return Ok(HashMap::new());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let modified = metadata.modified()?;
+        let len = metadata.len();
+
+        if let Some(cached) = cache.as_ref() {
+            if cached.modified == modified && cached.len == len {
+                return Ok(cached.values.clone());
+            }
+        }
+
+        let values = read_yaml_values(path)?;
+        *cache = Some(FileCache {
+            modified,
+            len,
+            values: values.clone(),
+        });
+        Ok(values)
+    }
+
+    /// Register a migration that upgrades config values from `from_version`
+    /// to `to_version`.
     ///
-    /// Note that this does not affect environment variables - those can only
-    /// be set through the system environment.
+    /// The current schema version is the highest `to_version` registered.
+    /// Stored values carry the version they were saved with under a reserved
+    /// key, and older values are migrated step by step the next time they are
+    /// loaded. Files without a version key count as version 0.
     ///
-    /// # Errors
+    /// Migrations must be idempotent: if they leave the values unchanged, the
+    /// file isn't rewritten and they run again on the next load.
     ///
-    /// Returns a ConfigError if:
-    /// - There is an error accessing the keyring
-    /// - There is an error serializing the value
-    pub fn set_secret(&self, key: &str, value: Value) -> Result<(), ConfigError> {
-        let mut values = self.load_secrets()?;
-        values.insert(key.to_string(), value);
+    /// # Panics
+    ///
+    /// Panics if `to_version` is not greater than `from_version`
+    pub fn register_migration<F>(&self, from_version: u32, to_version: u32, migration: F)
+    where
+        F: Fn(&mut HashMap<String, Value>) + Send + Sync + 'static,
+    {
+        assert!(
+            to_version > from_version,
+            "config migration from version {} must upgrade to a later version, not {}",
+            from_version,
+            to_version
+        );
+        self.migrations
+            .write()
+            .expect("migration registry lock poisoned")
+            .insert(from_version, (to_version, Box::new(migration)));
+    }
 
-        match &self.secrets {
-            SecretStorage::Keyring { service } => {
-                let json_value = serde_json::to_string(&values)?;
-                let entry = Entry::new(service, KEYRING_USERNAME)?;
-                entry.set_password(&json_value)?;
+    /// Upgrade the stored config to the current schema version.
+    ///
+    /// This also happens whenever values are loaded, so calling it is only
+    /// needed to upgrade eagerly, e.g. right after registering migrations.
+    pub fn migrate(&self) -> Result<(), ConfigError> {
+        self.load_values().map(|_| ())
+    }
+
+    // The schema version values are saved with
+    fn current_version(&self) -> u32 {
+        self.migrations
+            .read()
+            .expect("migration registry lock poisoned")
+            .values()
+            .map(|(to, _)| *to)
+            .fold(CONFIG_VERSION, u32::max)
+    }
+
+    // Strip the version key and run the chain of migrations from that version
+    // on, returning whether they changed the values and so need writing back
+    fn migrate_values(&self, values: &mut HashMap<String, Value>) -> bool {
+        let stored_version = take_version(values);
+        if stored_version >= self.current_version() {
+            return false;
+        }
+
+        let original = values.clone();
+        let migrations = self
+            .migrations
+            .read()
+            .expect("migration registry lock poisoned");
+        let mut version = stored_version;
+        // Each step moves to a strictly later version, so this terminates
+        while let Some((_, (to, migration))) = migrations.range(version..).next() {
+            migration(values);
+            version = *to;
+        }
+
+        if *values == original {
+            return false;
+        }
+        tracing::debug!(
+            "Migrated config from version {} to {}",
+            stored_version,
+            version
+        );
+        true
+    }
+
+    // Save current values to the config file
+    pub fn save_values(&self, mut values: HashMap<String, Value>) -> Result<(), ConfigError> {
+        self.param_cache
+            .lock()
+            .expect("param cache lock poisoned")
+            .clear();
+        *self.file_cache.lock().expect("file cache lock poisoned") = None;
+
+        values.insert(
+            CONFIG_VERSION_KEY.to_string(),
+            Value::from(self.current_version()),
+        );
+
+        let config_path = match &self.storage {
+            ConfigStorage::File { path } => path,
+            ConfigStorage::Memory { values: stored } => {
+                *stored.lock().expect("config values lock poisoned") = values;
+                return Ok(());
             }
-            SecretStorage::File { path } => {
-                let yaml_value = serde_yaml::to_string(&values)?;
-                std::fs::write(path, yaml_value)?;
+        };
+
+        // Convert to YAML for storage
+        let yaml_value = to_yaml_string(&values)?;
+        write_atomically(config_path, yaml_value.as_bytes())
+    }
+
+    // Acquire an exclusive lock that serializes read-modify-write cycles on the
+    // config, across threads and, for file storage, across processes. A sidecar
+    // file is locked rather than the config itself, so the lock is independent
+    // of how the config is written.
+    fn lock(&self) -> Result<ConfigLock<'_>, ConfigError> {
+        let guard = self
+            .write_lock
+            .lock()
+            .map_err(|e| ConfigError::LockError(e.to_string()))?;
+
+        let config_path = match &self.storage {
+            ConfigStorage::File { path } => path,
+            ConfigStorage::Memory { .. } => {
+                return Ok(ConfigLock {
+                    _guard: guard,
+                    _file: None,
+                });
             }
         };
-        Ok(())
+
+        if let Some(parent) = config_path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| ConfigError::DirectoryError(e.to_string()))?;
+        }
+
+        let mut lock_path = config_path.clone().into_os_string();
+        lock_path.push(".lock");
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(lock_path)?;
+        file.lock_exclusive()
+            .map_err(|e| ConfigError::LockError(e.to_string()))?;
+
+        // Unlock is handled automatically when the lock is dropped
+        Ok(ConfigLock {
+            _guard: guard,
+            _file: Some(file),
+        })
     }
 
-    /// Delete a secret from the system keyring.
+    /// Watch the config file and call `callback` with its values when it changes.
     ///
-    /// This will remove the specified key from the JSON object in the system keyring.
-    /// Other secrets will remain unchanged.
+    /// Changes are debounced: events are coalesced until the file has been
+    /// quiet for `debounce`, so editors that save in several steps (truncate,
+    /// then write) produce a single callback with the final content. The
+    /// callback is skipped when the parsed values are identical to the last
+    /// state it was given. Watching stops when the returned handle is dropped.
+    ///
+    /// In-memory configs never change on disk, so the handle is inert.
     ///
     /// # Errors
     ///
-    /// Returns a ConfigError if:
-    /// - There is an error accessing the keyring
-    /// - There is an error serializing the remaining values
-    pub fn delete_secret(&self, key: &str) -> Result<(), ConfigError> {
-        let mut values = self.load_secrets()?;
-        values.remove(key);
+    /// Returns a ConfigError if the file watcher can't be started
+    pub fn watch_values<F>(
+        &self,
+        debounce: Duration,
+        callback: F,
+    ) -> Result<WatchHandle, ConfigError>
+    where
+        F: Fn(HashMap<String, Value>) + Send + 'static,
+    {
+        let path = match &self.storage {
+            ConfigStorage::File { path } => path.clone(),
+            ConfigStorage::Memory { .. } => return Ok(WatchHandle::inactive()),
+        };
+
+        let load_path = path.clone();
+        watch::spawn_watcher(
+            path,
+            debounce,
+            move || {
+                read_yaml_values(&load_path).map(|mut values| {
+                    take_version(&mut values);
+                    values
+                })
+            },
+            move |values| {
+                callback(values.clone());
+                true
+            },
+        )
+    }
+
+    /// Subscribe to changes of the config file.
+    ///
+    /// Each time the file changes on disk, one [`ConfigChange`] is sent for
+    /// every key that was added, removed or modified. Changes are debounced
+    /// like [`Config::watch_values`], and the watch survives writes that
+    /// rename a temporary file over the config. Watching stops at the first
+    /// change after the receiver is dropped.
+    ///
+    /// In-memory configs never change on disk, so their receiver is closed.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if the file watcher can't be started
+    pub fn watch(&self) -> Result<Receiver<ConfigChange>, ConfigError> {
+        let (tx, rx) = mpsc::channel();
+        let path = match &self.storage {
+            ConfigStorage::File { path } => path.clone(),
+            ConfigStorage::Memory { .. } => return Ok(rx),
+        };
+
+        let load_path = path.clone();
+        let load = move || {
+            read_yaml_values(&load_path).map(|mut values| {
+                take_version(&mut values);
+                values
+            })
+        };
+        let mut last = load().unwrap_or_default();
+        let file_cache = self.file_cache.clone();
+        let handle = watch::spawn_watcher(path, DEFAULT_WATCH_DEBOUNCE, load, move |values| {
+            *file_cache.lock().expect("file cache lock poisoned") = None;
+            let changes = watch::diff_values(&last, values);
+            last = values.clone();
+            changes.into_iter().all(|change| tx.send(change).is_ok())
+        })?;
+        handle.detach();
+        Ok(rx)
+    }
+
+    /// Watch a single key and call `callback` with its new value when it changes.
+    ///
+    /// This builds on the same debounced file watcher as [`Config::watch_values`],
+    /// but changes to other keys are ignored. Deleting the key calls `callback`
+    /// with `None`. Watching stops when the returned handle is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if the file watcher can't be started
+    pub fn watch_key<F>(&self, key: &str, callback: F) -> Result<WatchHandle, ConfigError>
+    where
+        F: Fn(Option<Value>) + Send + 'static,
+    {
+        let path = match &self.storage {
+            ConfigStorage::File { path } => path.clone(),
+            ConfigStorage::Memory { .. } => return Ok(WatchHandle::inactive()),
+        };
+
+        let key = key.to_string();
+        let mut last = read_yaml_values(&path)
+            .ok()
+            .and_then(|values| values.get(&key).cloned());
+        let load_path = path.clone();
+        watch::spawn_watcher(
+            path,
+            DEFAULT_WATCH_DEBOUNCE,
+            move || read_yaml_values(&load_path),
+            move |values| {
+                let current = values.get(&key).cloned();
+                if current != last {
+                    last = current.clone();
+                    callback(current);
+                }
+                true
+            },
+        )
+    }
+
+    /// Start staging changes to be written together.
+    ///
+    /// See [`Transaction`] for details.
+    pub fn begin_transaction(&self) -> Transaction<'_> {
+        Transaction {
+            config: self,
+            staged: HashMap::new(),
+        }
+    }
+
+    /// Get a view of the params whose keys start with `prefix`.
+    ///
+    /// See [`ScopedConfig`] for details.
+    pub fn scoped(&self, prefix: &str) -> ScopedConfig<'_> {
+        ScopedConfig {
+            config: self,
+            prefix: prefix.to_string(),
+            separator: "_".to_string(),
+        }
+    }
+
+    /// Copy all params and secrets from this config into `dest`.
+    ///
+    /// Params are merged into the destination's stored values and written in a
+    /// single save, then the secrets are merged into its secret store the same
+    /// way. Keys that only exist in `dest` are left untouched. This is the natural way to
+    /// persist a config built up in memory, e.g. at the end of onboarding.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if either config can't be read, or `dest` can't be written
+    pub fn clone_to(&self, dest: &Config) -> Result<(), ConfigError> {
+        let values = self.load_values()?;
+        let secrets = self.load_secrets()?;
+
+        let lock = dest.lock()?;
+        let mut dest_values = dest.load_values_locked(&lock)?;
+        dest_values.extend(values);
+        dest.save_values(dest_values)?;
 
+        if !secrets.is_empty() {
+            let mut dest_secrets = dest.load_secret_store()?;
+            dest_secrets.extend(secrets);
+            dest.save_secrets(dest_secrets)?;
+        }
+
+        Ok(())
+    }
+
+    /// Check whether the secret backend can be reached.
+    ///
+    /// For the keyring this probes the secrets entry; a missing entry still
+    /// counts as available, while platform or locked-storage failures do not.
+    /// The keyring itself is probed even when a fallback file is configured or
+    /// already in use, and probing never changes where secrets are stored, so
+    /// apps can use this to tell the user the keyring is down. File and
+    /// in-memory secret storage are always available.
+    pub fn secret_backend_available(&self) -> bool {
         match &self.secrets {
-            SecretStorage::Keyring { service } => {
-                let json_value = serde_json::to_string(&values)?;
-                let entry = Entry::new(service, KEYRING_USERNAME)?;
-                entry.set_password(&json_value)?;
+            SecretStorage::Keyring {
+                service,
+                username,
+                backend,
+                ..
+            } => match backend.get_password(service, username) {
+                Ok(_) | Err(keyring::Error::NoEntry) => true,
+                Err(e) => {
+                    tracing::debug!("Secret keyring is unavailable: {}", e);
+                    false
+                }
+            },
+            SecretStorage::File { .. } | SecretStorage::Memory { .. } | SecretStorage::EnvOnly => {
+                true
             }
-            SecretStorage::File { path } => {
-                let yaml_value = serde_yaml::to_string(&values)?;
-                std::fs::write(path, yaml_value)?;
+        }
+    }
+
+    /// Report where secrets are actually stored.
+    ///
+    /// For a keyring with a fallback file this probes the keyring if it
+    /// hasn't been used yet, so the answer reflects a fallback that would
+    /// happen on first access.
+    pub fn secret_backend(&self) -> SecretBackend {
+        match &self.secrets {
+            SecretStorage::Keyring {
+                service,
+                username,
+                backend,
+                ..
+            } => {
+                if self.fallback_path().is_none() {
+                    if let Err(e) = backend.get_password(service, username) {
+                        let _ = self.fall_back_from_keyring(e);
+                    }
+                }
+                match self.fallback_path() {
+                    Some(path) => SecretBackend::File(path.to_path_buf()),
+                    None => SecretBackend::Keyring,
+                }
             }
-        };
-        Ok(())
+            SecretStorage::File { path } => SecretBackend::File(path.clone()),
+            SecretStorage::Memory { .. } => SecretBackend::Memory,
+            SecretStorage::EnvOnly => SecretBackend::Env,
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use serial_test::serial;
-    use tempfile::NamedTempFile;
+    // The fallback secrets file, once the keyring has been found unusable
+    fn fallback_path(&self) -> Option<&Path> {
+        match &self.secrets {
+            SecretStorage::Keyring {
+                fallback: Some(path),
+                ..
+            } if self.keyring_fallen_back.load(Ordering::SeqCst) => Some(path),
+            _ => None,
+        }
+    }
 
-    fn cleanup_keyring() -> Result<(), ConfigError> {
-        let entry = Entry::new(TEST_KEYRING_SERVICE, KEYRING_USERNAME)?;
-        match entry.delete_credential() {
-            Ok(_) => Ok(()),
-            Err(keyring::Error::NoEntry) => Ok(()),
-            Err(e) => Err(ConfigError::KeyringError(e.to_string())),
+    // Switch to the fallback secrets file if `err` means the keyring can't be
+    // reached and a fallback is configured, otherwise return the error
+    fn fall_back_from_keyring(&self, err: keyring::Error) -> Result<&Path, ConfigError> {
+        let err = ConfigError::from(err);
+        let path = match (&self.secrets, &err) {
+            (
+                SecretStorage::Keyring {
+                    fallback: Some(path),
+                    ..
+                },
+                ConfigError::SecretBackendUnavailable(_),
+            ) => path,
+            _ => return Err(err),
+        };
+
+        if !self.keyring_fallen_back.swap(true, Ordering::SeqCst) {
+            tracing::warn!(
+                "{}; storing secrets in {} instead (set {} to disable this fallback)",
+                err,
+                path.display(),
+                KEYRING_STRICT_ENV
+            );
         }
+        Ok(path)
     }
 
-    #[test]
-    fn test_basic_config() -> Result<(), ConfigError> {
-        let temp_file = NamedTempFile::new().unwrap();
-        let config = Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?;
+    /// Load the current secrets from the secret store.
+    ///
+    /// Previous values kept by [`Config::rotate_secret`] are not included;
+    /// they are only readable through [`Config::get_previous_secret`].
+    pub fn load_secrets(&self) -> Result<HashMap<String, Value>, ConfigError> {
+        Ok(self
+            .load_secret_store()?
+            .into_iter()
+            .filter(|(key, _)| key != PREVIOUS_SECRETS_KEY)
+            .collect())
+    }
 
-        // Set a simple string value
-        config.set_param("test_key", Value::String("test_value".to_string()))?;
+    // Load everything in the secret store, including previous values kept by
+    // rotate_secret. Read-modify-write cycles use this so they keep them.
+    fn load_secret_store(&self) -> Result<HashMap<String, Value>, ConfigError> {
+        if let Some(path) = self.fallback_path() {
+            return read_secrets_file(path);
+        }
 
-        // Test simple string retrieval
-        let value: String = config.get_param("test_key")?;
-        assert_eq!(value, "test_value");
+        match &self.secrets {
+            SecretStorage::Keyring {
+                service,
+                username,
+                backend,
+                ..
+            } => {
+                match backend.get_password(service, username) {
+                    Ok(content) => {
+                        let values: HashMap<String, Value> = serde_json::from_str(&content)?;
+                        Ok(values)
+                    }
+                    // The keyring is reachable but nothing has been stored yet
+                    Err(keyring::Error::NoEntry) => Ok(HashMap::new()),
+                    Err(e) => read_secrets_file(self.fall_back_from_keyring(e)?),
+                }
+            }
+            SecretStorage::File { path } => read_secrets_file(path),
+            SecretStorage::Memory { values } => values
+                .lock()
+                .expect("secret values lock poisoned")
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), serde_json::from_str(value)?)))
+                .collect(),
+            SecretStorage::EnvOnly => Ok(HashMap::new()),
+        }
+    }
 
-        // Test with environment variable override
-        std::env::set_var("TEST_KEY", "env_value");
-        let value: String = config.get_param("test_key")?;
-        assert_eq!(value, "env_value");
+    /// Register a key as secret, regardless of how callers store it.
+    ///
+    /// Marked keys are routed to the secret store by [`Config::get`] and
+    /// [`Config::set`] even when `is_secret` is false, and [`Config::set_param`]
+    /// redirects them to [`Config::set_secret`] so they never land in the
+    /// plaintext config file.
+    pub fn mark_secret(&self, key: &str) {
+        self.secret_keys
+            .write()
+            .expect("secret key registry lock poisoned")
+            .insert(key.to_string());
+    }
 
-        Ok(())
+    /// Check whether a key has been registered with [`Config::mark_secret`]
+    pub fn is_marked_secret(&self, key: &str) -> bool {
+        self.secret_keys
+            .read()
+            .expect("secret key registry lock poisoned")
+            .contains(key)
     }
 
-    #[test]
-    fn test_complex_type() -> Result<(), ConfigError> {
-        #[derive(Deserialize, Debug, PartialEq)]
-        struct TestStruct {
-            field1: String,
-            field2: i32,
+    // check all possible places for a parameter
+    pub fn get(&self, key: &str, is_secret: bool) -> Result<Value, ConfigError> {
+        if is_secret || self.is_marked_secret(key) {
+            self.get_secret(key)
+        } else {
+            self.get_param(key)
         }
+    }
 
-        let temp_file = NamedTempFile::new().unwrap();
-        let config = Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?;
+    // save a parameter in the appropriate location based on if it's secret or not
+    pub fn set(&self, key: &str, value: Value, is_secret: bool) -> Result<(), ConfigError> {
+        if is_secret || self.is_marked_secret(key) {
+            self.set_secret(key, value)
+        } else {
+            self.set_param(key, value)
+        }
+    }
 
-        // Set a complex value
-        config.set_param(
-            "complex_key",
-            serde_json::json!({
-                "field1": "hello",
-                "field2": 42
-            }),
-        )?;
+    /// Build the effective configuration for display.
+    ///
+    /// Returns a JSON object holding every param from the config file and the
+    /// defaults at the top level, with environment overrides applied to their
+    /// values, plus a `secrets` object mapping each stored secret key to
+    /// `"***"` so values are never exposed. A param that is itself named
+    /// `secrets` is shadowed by that object and left out. Keys that only
+    /// exist in the environment are not included since they can't be
+    /// enumerated.
+    pub fn effective_config(&self) -> Result<Value, ConfigError> {
+        let mut values = self.defaults.clone();
+        values.extend(self.load_values()?);
+        let mut params: BTreeMap<String, Value> = values
+            .into_iter()
+            .map(|(key, value)| {
+                let value = match self.env_var(&key) {
+                    Some(raw) => coerce_env_value(&raw, Some(&value)),
+                    None => value,
+                };
+                (key, value)
+            })
+            .collect();
 
-        let value: TestStruct = config.get_param("complex_key")?;
-        assert_eq!(value.field1, "hello");
-        assert_eq!(value.field2, 42);
+        if params.contains_key("secrets") {
+            tracing::warn!(
+                "The 'secrets' param is shadowed by the secrets in the effective config"
+            );
+        }
+        let secrets = self
+            .load_secrets()?
+            .into_keys()
+            .map(|key| (key, Value::String(REDACTED.to_string())))
+            .collect();
+        params.insert("secrets".to_string(), Value::Object(secrets));
 
-        Ok(())
+        Ok(serde_json::to_value(params)?)
     }
 
-    #[test]
-    fn test_missing_value() {
-        let temp_file = NamedTempFile::new().unwrap();
-        let config = Config::new(temp_file.path(), TEST_KEYRING_SERVICE).unwrap();
+    /// Export the stored params and secrets as a single JSON document.
+    ///
+    /// Secret values are redacted to `"***"`, keeping only their names, unless
+    /// `opts.include_secrets` is set, so by default the export is safe to
+    /// share for diagnostics. Environment overrides and defaults are not
+    /// included; see [`Config::effective_config`] for those.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if the config or the secret store can't be read
+    pub fn export(&self, opts: ExportOptions) -> Result<String, ConfigError> {
+        let secrets = self
+            .load_secrets()?
+            .into_iter()
+            .map(|(key, value)| {
+                if opts.include_secrets {
+                    (key, value)
+                } else {
+                    (key, Value::String(REDACTED.to_string()))
+                }
+            })
+            .collect();
+        let snapshot = ConfigSnapshot {
+            params: self.load_values()?.into_iter().collect(),
+            secrets,
+        };
+        Ok(serde_json::to_string_pretty(&snapshot)?)
+    }
 
-        let result: Result<String, ConfigError> = config.get_param("nonexistent_key");
-        assert!(matches!(result, Err(ConfigError::NotFound(_))));
+    /// Import a document written by [`Config::export`].
+    ///
+    /// With `merge`, the imported params and secrets are added to the stored
+    /// ones, replacing keys that exist in both. Otherwise the stored params are
+    /// replaced entirely, as are the secrets if the export included them.
+    /// Redacted secrets are skipped, so importing a redacted export never
+    /// changes stored secret values.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if the document can't be parsed, or the config or
+    /// the secret store can't be written
+    pub fn import(&self, json: &str, merge: bool) -> Result<(), ConfigError> {
+        let snapshot: ConfigSnapshot = serde_json::from_str(json)?;
+        let redacted = Value::String(REDACTED.to_string());
+        let secrets_redacted = snapshot.secrets.values().any(|value| *value == redacted);
+        let secrets: HashMap<String, Value> = snapshot
+            .secrets
+            .into_iter()
+            .filter(|(_, value)| *value != redacted)
+            .collect();
+
+        let lock = self.lock()?;
+        let mut values = if merge {
+            self.load_values_locked(&lock)?
+        } else {
+            HashMap::new()
+        };
+        values.extend(snapshot.params);
+        self.save_values(values)?;
+
+        if merge || secrets_redacted {
+            if !secrets.is_empty() {
+                let mut stored = self.load_secret_store()?;
+                stored.extend(secrets);
+                self.save_secrets(stored)?;
+            }
+        } else {
+            self.save_secrets(secrets)?;
+        }
+        Ok(())
     }
 
-    #[test]
-    fn test_yaml_formatting() -> Result<(), ConfigError> {
-        let temp_file = NamedTempFile::new().unwrap();
-        let config = Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?;
+    /// Get a configuration value (non-secret).
+    ///
+    /// This will attempt to get the value from:
+    /// 1. Environment variable with the exact key name
+    /// 2. Configuration file
+    /// 3. Defaults set with [`Config::with_defaults`]
+    ///
+    /// The value will be deserialized into the requested type. This works with
+    /// both simple types (String, i32, etc.) and complex types that implement
+    /// serde::Deserialize.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if:
+    /// - The key doesn't exist in the environment, config file or defaults
+    /// - The value cannot be deserialized into the requested type
+    /// - There is an error reading the config file
+    pub fn get_param<T: for<'de> Deserialize<'de>>(&self, key: &str) -> Result<T, ConfigError> {
+        let (value, _) = self.resolve_param(key)?;
+        deserialize_value(key, value)
+    }
+
+    /// Get a configuration value, or `default` if it isn't set anywhere.
+    ///
+    /// Only a missing key falls back to `default`. A value that is present but
+    /// can't be deserialized, or a config file that can't be read, is still
+    /// returned as an error rather than silently replaced.
+    pub fn get_param_or<T: for<'de> Deserialize<'de>>(
+        &self,
+        key: &str,
+        default: T,
+    ) -> Result<T, ConfigError> {
+        match self.get_param(key) {
+            Err(ConfigError::NotFound(_)) => Ok(default),
+            result => result,
+        }
+    }
+
+    /// Check whether [`Config::get_param`] would find a value for `key`, in the
+    /// environment, the stored values or the defaults, whatever its type.
+    ///
+    /// A config file that can't be read counts as not containing the key.
+    pub fn contains_param(&self, key: &str) -> bool {
+        self.env_override(key).is_some()
+            || self
+                .load_values()
+                .is_ok_and(|values| values.contains_key(key))
+            || self.defaults.contains_key(key)
+    }
+
+    /// Get a configuration value, reusing the last read for up to `ttl`.
+    ///
+    /// This is meant for hot paths that read a rarely changing value on every
+    /// call. Within `ttl` the value is served from memory without touching the
+    /// config file; afterwards it is resolved again as with
+    /// [`Config::get_param`]. Writes through this config (e.g.
+    /// [`Config::set_param`]) invalidate the cache, but changes made by other
+    /// processes or to environment variables may take up to `ttl` to show.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`Config::get_param`]
+    pub fn get_param_cached<T: for<'de> Deserialize<'de>>(
+        &self,
+        key: &str,
+        ttl: Duration,
+    ) -> Result<T, ConfigError> {
+        let cached = self
+            .param_cache
+            .lock()
+            .expect("param cache lock poisoned")
+            .get(key)
+            .filter(|(read_at, _)| read_at.elapsed() < ttl)
+            .map(|(_, value)| value.clone());
+
+        let value = match cached {
+            Some(value) => value,
+            None => {
+                let (value, _) = self.resolve_param(key)?;
+                self.param_cache
+                    .lock()
+                    .expect("param cache lock poisoned")
+                    .insert(key.to_string(), (Instant::now(), value.clone()));
+                value
+            }
+        };
+        deserialize_value(key, value)
+    }
+
+    /// Report where [`Config::get_param`] would get the value of `key` from.
+    ///
+    /// This lets a settings UI mark values that are still at their default.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ConfigError::NotFound`] if the key isn't set anywhere, or an
+    /// error reading the config file
+    pub fn get_param_source(&self, key: &str) -> Result<ValueSource, ConfigError> {
+        self.resolve_param(key).map(|(_, source)| source)
+    }
+
+    /// Load a dotenv file as a layer of overrides, without touching the
+    /// process environment.
+    ///
+    /// `path` defaults to `.env` in the current directory; a missing default
+    /// file just clears the layer, while a missing explicit path is an error.
+    /// Keys follow the environment variable convention, so `GOOSE_MODEL=...`
+    /// overrides the `GOOSE_MODEL` param.
+    ///
+    /// For [`Config::get_param`] and [`Config::get_secret`] the precedence is:
+    /// 1. a real environment variable
+    /// 2. a dotenv override
+    /// 3. the config file or secret store
+    /// 4. the defaults
+    ///
+    /// Loading again replaces the previous overrides rather than adding to
+    /// them, so a changed `.env` can simply be reloaded.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if the file can't be read or parsed, in which case
+    /// the previous overrides are kept
+    pub fn load_dotenv(&self, path: Option<&Path>) -> Result<(), ConfigError> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => (PathBuf::from(DOTENV_FILE), false),
+        };
+
+        let mut overrides = HashMap::new();
+        if required || path.exists() {
+            // The replacement dotenv suggests, from_path, sets the process
+            // environment, which is what this layer avoids
+            #[allow(deprecated)]
+            for entry in dotenv::from_path_iter(&path)? {
+                let (key, value) = entry?;
+                overrides.insert(key.to_uppercase(), value);
+            }
+        }
+
+        *self.dotenv.write().expect("dotenv lock poisoned") = overrides;
+        self.param_cache
+            .lock()
+            .expect("param cache lock poisoned")
+            .clear();
+        Ok(())
+    }
+
+    /// Look up the raw override for a key, using the uppercase key name: the
+    /// process environment first, then the dotenv overrides.
+    fn env_var(&self, key: &str) -> Option<String> {
+        let key = key.to_uppercase();
+        env::var(&key).ok().or_else(|| {
+            self.dotenv
+                .read()
+                .expect("dotenv lock poisoned")
+                .get(&key)
+                .cloned()
+        })
+    }
+
+    /// Look up the override for a key, without a known shape to coerce it
+    /// to. See [`coerce_env_value`].
+    fn env_override(&self, key: &str) -> Option<Value> {
+        self.env_var(key).map(|raw| coerce_env_value(&raw, None))
+    }
+
+    // Look a param up in the environment, then the stored values, then the defaults
+    fn resolve_param(&self, key: &str) -> Result<(Value, ValueSource), ConfigError> {
+        // First check environment variables (convert to uppercase)
+        if let Some(raw) = self.env_var(key) {
+            let hint = self.param_hint(key);
+            return Ok((coerce_env_value(&raw, hint.as_ref()), ValueSource::Env));
+        }
+
+        // Then check our stored values
+        if let Some(value) = self.load_values()?.remove(key) {
+            return Ok((value, ValueSource::File));
+        }
+
+        self.defaults
+            .get(key)
+            .map(|value| (value.clone(), ValueSource::Default))
+            .ok_or_else(|| ConfigError::NotFound(key.to_string()))
+    }
+
+    // The stored or default value an env override of `key` shadows, used to
+    // coerce the override to the same shape. An unreadable config file gives
+    // no hint rather than failing the lookup.
+    fn param_hint(&self, key: &str) -> Option<Value> {
+        self.load_values()
+            .ok()
+            .and_then(|mut values| values.remove(key))
+            .or_else(|| self.defaults.get(key).cloned())
+    }
+
+    /// Get the first of several candidate configuration values that is set.
+    ///
+    /// Each key is tried in order with [`Config::get_param`], so both the
+    /// environment and the config file are checked for a key before moving on
+    /// to the next. This is useful when supporting legacy names alongside new
+    /// ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ConfigError::NotFound`] listing every key tried if none is
+    /// set, or the error from the first key that is set but can't be read.
+    pub fn get_param_any<T: for<'de> Deserialize<'de>>(
+        &self,
+        keys: &[&str],
+    ) -> Result<T, ConfigError> {
+        first_found(keys, |key| self.get_param(key))
+    }
+
+    /// Set a configuration value in the config file (non-secret).
+    ///
+    /// This will immediately write the value to the config file. The value
+    /// can be any type that can be serialized to JSON/YAML.
+    ///
+    /// Note that this does not affect environment variables - those can only
+    /// be set through the system environment.
+    ///
+    /// Keys registered with [`Config::mark_secret`] are stored as secrets
+    /// instead, so they never end up in the plaintext config file.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if:
+    /// - There is an error reading or writing the config file
+    /// - There is an error serializing the value
+    pub fn set_param(&self, key: &str, value: Value) -> Result<(), ConfigError> {
+        if self.is_marked_secret(key) {
+            tracing::warn!(
+                "Config key '{}' is registered as secret, storing it in the secret store",
+                key
+            );
+            return self.set_secret(key, value);
+        }
+
+        let lock = self.lock()?;
+        let mut values = self.load_values_locked(&lock)?;
+        values.insert(key.to_string(), value);
+
+        self.save_values(values)
+    }
+
+    /// Get a value from an already loaded map of values.
+    ///
+    /// Together with [`Config::set_in`] this lets callers load the values once
+    /// with [`Config::load_values`], read and change many keys, and write them
+    /// back once with [`Config::save_values`], e.g. under their own locking.
+    /// Only `values` is consulted; environment variables are not.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if the key is missing or can't be deserialized
+    pub fn get_from<T: for<'de> Deserialize<'de>>(
+        &self,
+        values: &HashMap<String, Value>,
+        key: &str,
+    ) -> Result<T, ConfigError> {
+        values
+            .get(key)
+            .ok_or_else(|| ConfigError::NotFound(key.to_string()))
+            .and_then(|v| deserialize_value(key, v.clone()))
+    }
+
+    /// Set a value in an already loaded map of values.
+    ///
+    /// Nothing is written until the map is passed to [`Config::save_values`].
+    pub fn set_in(values: &mut HashMap<String, Value>, key: &str, value: Value) {
+        values.insert(key.to_string(), value);
+    }
+
+    /// Replace the whole config with exactly the given values.
+    ///
+    /// Unlike [`Config::set_param`], keys missing from `values` are removed, so
+    /// this suits applying a complete preset. The write happens under the
+    /// config lock in a single save.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if the config file can't be written
+    pub fn set_all(&self, values: HashMap<String, Value>) -> Result<(), ConfigError> {
+        let _lock = self.lock()?;
+        self.save_values(values)
+    }
+
+    /// Get a configuration value, or compute, persist and return it if absent.
+    ///
+    /// The lookup follows the same precedence as [`Config::get_param`], so a
+    /// key served by a default from [`Config::with_defaults`] returns it
+    /// without calling `init`. When the key isn't set anywhere, `init` is
+    /// called once and its result is written to the config file. The whole read-check-write happens under the config
+    /// lock, so concurrent callers (including other processes) agree on a single
+    /// generated value.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if:
+    /// - The existing value cannot be deserialized into the requested type
+    /// - There is an error reading or writing the config file
+    /// - There is an error serializing the computed value
+    pub fn get_or_set_with<T, F>(&self, key: &str, init: F) -> Result<T, ConfigError>
+    where
+        T: Serialize + for<'de> Deserialize<'de>,
+        F: FnOnce() -> T,
+    {
+        if let Some(raw) = self.env_var(key) {
+            let hint = self.param_hint(key);
+            return deserialize_value(key, coerce_env_value(&raw, hint.as_ref()));
+        }
+
+        let lock = self.lock()?;
+        let mut values = self.load_values_locked(&lock)?;
+        if let Some(value) = values.get(key).or_else(|| self.defaults.get(key)) {
+            return deserialize_value(key, value.clone());
+        }
+
+        let value = init();
+        values.insert(key.to_string(), serde_json::to_value(&value)?);
+        self.save_values(values)?;
+
+        Ok(value)
+    }
+
+    /// Delete a configuration value in the config file.
+    ///
+    /// This will immediately write the value to the config file. The value
+    /// can be any type that can be serialized to JSON/YAML.
+    ///
+    /// Note that this does not affect environment variables - those can only
+    /// be set through the system environment.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if:
+    /// - There is an error reading or writing the config file
+    /// - There is an error serializing the value
+    pub fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let lock = self.lock()?;
+        let mut values = self.load_values_locked(&lock)?;
+        values.remove(key);
+
+        self.save_values(values)
+    }
+
+    /// Set several configuration values with a single write.
+    ///
+    /// The config is loaded once, all values are applied in memory and the
+    /// result is saved once under the config lock, so either every value is
+    /// written or none is. Keys registered with [`Config::mark_secret`] are
+    /// stored in the secret store instead, in the same way. The secrets are
+    /// written first; if the config then can't be written, the previous
+    /// secrets are restored.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if the config or the secret store can't be read
+    /// or written, or the values can't be serialized
+    pub fn set_many(&self, values: HashMap<String, Value>) -> Result<(), ConfigError> {
+        let (secrets, params): (HashMap<_, _>, HashMap<_, _>) = values
+            .into_iter()
+            .partition(|(key, _)| self.is_marked_secret(key));
+
+        if !secrets.is_empty() {
+            tracing::warn!(
+                "Config keys {:?} are registered as secret, storing them in the secret store",
+                secrets.keys().collect::<Vec<_>>()
+            );
+        }
+
+        let lock = self.lock()?;
+        let mut stored = self.load_values_locked(&lock)?;
+        stored.extend(params);
+        let secrets = secrets
+            .into_iter()
+            .map(|(key, value)| (key, Some(value)))
+            .collect();
+        self.save_values_with_secrets(&lock, stored, secrets)
+    }
+
+    // Save the params and apply the secret changes (None deletes a secret)
+    // under the held lock, so either both are written or neither is. The
+    // secrets are written first, in one write, and restored if the params
+    // then can't be saved.
+    fn save_values_with_secrets(
+        &self,
+        _lock: &ConfigLock,
+        values: HashMap<String, Value>,
+        secrets: HashMap<String, Option<Value>>,
+    ) -> Result<(), ConfigError> {
+        // Fail on values that can't be written before touching either store
+        to_yaml_string(&values)?;
+        if secrets.is_empty() {
+            return self.save_values(values);
+        }
+
+        let previous = self.load_secret_store()?;
+        let mut updated = previous.clone();
+        let mut rotated = take_previous_secrets(&mut updated);
+        for (key, value) in secrets {
+            match value {
+                Some(value) => {
+                    updated.insert(key, value);
+                }
+                None => {
+                    updated.remove(&key);
+                    rotated.remove(&key);
+                }
+            }
+        }
+        put_previous_secrets(&mut updated, rotated)?;
+        self.save_secrets(updated)?;
+
+        if let Err(err) = self.save_values(values) {
+            if let Err(restore_err) = self.save_secrets(previous) {
+                tracing::warn!("Failed to restore secrets: {}", restore_err);
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Delete several configuration values with a single write.
+    ///
+    /// Like [`Config::set_many`], the config is loaded and saved once.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if the config file can't be read or written
+    pub fn delete_many(&self, keys: &[String]) -> Result<(), ConfigError> {
+        let lock = self.lock()?;
+        let mut values = self.load_values_locked(&lock)?;
+        for key in keys {
+            values.remove(key);
+        }
+
+        self.save_values(values)
+    }
+
+    /// Get a secret value.
+    ///
+    /// This will attempt to get the value from:
+    /// 1. Environment variable with the exact key name
+    /// 2. System keyring
+    ///
+    /// The value will be deserialized into the requested type. This works with
+    /// both simple types (String, i32, etc.) and complex types that implement
+    /// serde::Deserialize.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if:
+    /// - The key doesn't exist in either environment or keyring (`NotFound`)
+    /// - The secret backend itself can't be reached (`SecretBackendUnavailable`)
+    /// - The value cannot be deserialized into the requested type
+    /// - There is an error accessing the keyring
+    pub fn get_secret<T: for<'de> Deserialize<'de>>(&self, key: &str) -> Result<T, ConfigError> {
+        // First check environment variables (convert to uppercase). The secret
+        // store isn't read for a type hint, so an override works even when the
+        // keyring is unavailable.
+        if let Some(value) = self.env_override(key) {
+            return deserialize_value(key, value);
+        }
+
+        // Then check keyring
+        let values = self.load_secrets()?;
+        values
+            .get(key)
+            .ok_or_else(|| ConfigError::NotFound(key.to_string()))
+            .and_then(|v| deserialize_value(key, v.clone()))
+    }
+
+    /// Get the first of several candidate secrets that is set.
+    ///
+    /// The secret analog of [`Config::get_param_any`], trying each key in
+    /// order with [`Config::get_secret`].
+    pub fn get_secret_any<T: for<'de> Deserialize<'de>>(
+        &self,
+        keys: &[&str],
+    ) -> Result<T, ConfigError> {
+        first_found(keys, |key| self.get_secret(key))
+    }
+
+    /// Check whether [`Config::get_secret`] would find a value for `key`, in
+    /// the environment or the secret store, without reading it as any type.
+    ///
+    /// A secret store that can't be read counts as not containing the key.
+    pub fn contains_secret(&self, key: &str) -> bool {
+        self.env_override(key).is_some()
+            || self
+                .load_secrets()
+                .is_ok_and(|values| values.contains_key(key))
+    }
+
+    /// Set a secret value in the system keyring.
+    ///
+    /// This will store the value in a single JSON object in the system keyring,
+    /// alongside any other secrets. The value can be any type that can be
+    /// serialized to JSON.
+    ///
+    /// Note that this does not affect environment variables - those can only
+    /// be set through the system environment.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if:
+    /// - There is an error accessing the keyring
+    /// - There is an error serializing the value
+    pub fn set_secret(&self, key: &str, value: Value) -> Result<(), ConfigError> {
+        let _lock = self.lock()?;
+        let mut values = self.load_secret_store()?;
+        values.insert(key.to_string(), value);
+
+        self.save_secrets(values)
+    }
+
+    /// Set several secrets with a single write to the secret store.
+    ///
+    /// For the keyring this re-serializes and stores the secrets entry once
+    /// rather than once per secret. Either every secret is written or none is.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if the secret store can't be read or written, or
+    /// the values can't be serialized
+    pub fn set_secrets_many(&self, secrets: HashMap<String, Value>) -> Result<(), ConfigError> {
+        let _lock = self.lock()?;
+        let mut values = self.load_secret_store()?;
+        values.extend(secrets);
+
+        self.save_secrets(values)
+    }
+
+    /// Replace a secret while keeping the old value readable for a grace period.
+    ///
+    /// The new value is stored under `key`, and the current value (if any) is
+    /// kept alongside it with an expiry of `grace` from now.
+    /// [`Config::get_previous_secret`] returns it until then, so in-flight
+    /// requests using the old credential keep working during the rotation.
+    /// Previous values whose grace period has passed are removed on the next
+    /// rotation of any secret.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if:
+    /// - There is an error accessing the secret storage
+    /// - There is an error serializing the values
+    pub fn rotate_secret(&self, key: &str, new: Value, grace: Duration) -> Result<(), ConfigError> {
+        let _lock = self.lock()?;
+        let now = chrono::Utc::now().timestamp_millis();
+        let mut values = self.load_secret_store()?;
+        let mut previous = take_previous_secrets(&mut values);
+        previous.retain(|_, previous| previous.expires_at > now);
+
+        match values.insert(key.to_string(), new) {
+            Some(old) => {
+                let replaced = PreviousSecret {
+                    value: old,
+                    expires_at: now + grace.as_millis() as i64,
+                };
+                previous.insert(key.to_string(), replaced);
+            }
+            None => {
+                previous.remove(key);
+            }
+        }
+
+        put_previous_secrets(&mut values, previous)?;
+        self.save_secrets(values)
+    }
+
+    /// Get the value a secret had before its last [`Config::rotate_secret`].
+    ///
+    /// A previous value whose grace period has passed is removed from the
+    /// secret store.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if:
+    /// - There is no previous value, or its grace period has passed (`NotFound`)
+    /// - The value cannot be deserialized into the requested type
+    /// - There is an error accessing the secret storage
+    pub fn get_previous_secret<T: for<'de> Deserialize<'de>>(
+        &self,
+        key: &str,
+    ) -> Result<T, ConfigError> {
+        let now = chrono::Utc::now().timestamp_millis();
+        match take_previous_secrets(&mut self.load_secret_store()?).remove(key) {
+            Some(previous) if previous.expires_at > now => {
+                return deserialize_value(key, previous.value)
+            }
+            Some(_) => {}
+            None => return Err(ConfigError::NotFound(key.to_string())),
+        }
+
+        // Check again under the lock, since a rotation may have replaced the
+        // expired value since it was read
+        let _lock = self.lock()?;
+        let mut values = self.load_secret_store()?;
+        let mut previous = take_previous_secrets(&mut values);
+        match previous.remove(key) {
+            Some(fresh) if fresh.expires_at > now => deserialize_value(key, fresh.value),
+            Some(_) => {
+                put_previous_secrets(&mut values, previous)?;
+                self.save_secrets(values)?;
+                Err(ConfigError::NotFound(key.to_string()))
+            }
+            None => Err(ConfigError::NotFound(key.to_string())),
+        }
+    }
+
+    /// Replace all stored secrets with exactly the given values.
+    ///
+    /// The secret analog of [`Config::set_all`]: secrets missing from `values`
+    /// are removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if the secrets can't be written
+    pub fn set_all_secrets(&self, values: HashMap<String, Value>) -> Result<(), ConfigError> {
+        let _lock = self.lock()?;
+        self.save_secrets(values)
+    }
+
+    /// Delete a secret from the system keyring.
+    ///
+    /// This will remove the specified key from the JSON object in the system keyring,
+    /// along with any previous value kept by [`Config::rotate_secret`].
+    /// Other secrets will remain unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if:
+    /// - There is an error accessing the keyring
+    /// - There is an error serializing the remaining values
+    pub fn delete_secret(&self, key: &str) -> Result<(), ConfigError> {
+        let _lock = self.lock()?;
+        let mut values = self.load_secret_store()?;
+        values.remove(key);
+        let mut previous = take_previous_secrets(&mut values);
+        previous.remove(key);
+        put_previous_secrets(&mut values, previous)?;
+
+        self.save_secrets(values)
+    }
+
+    // Replace all stored secrets with the given values
+    fn save_secrets(&self, values: HashMap<String, Value>) -> Result<(), ConfigError> {
+        if let Some(path) = self.fallback_path() {
+            return write_secrets_file(path, &values);
+        }
+
+        match &self.secrets {
+            SecretStorage::Keyring {
+                service,
+                username,
+                backend,
+                ..
+            } => {
+                let json_value = Zeroizing::new(serde_json::to_string(&values)?);
+                if let Err(e) = backend.set_password(service, username, &json_value) {
+                    write_secrets_file(self.fall_back_from_keyring(e)?, &values)?;
+                }
+            }
+            SecretStorage::File { path } => write_secrets_file(path, &values)?,
+            SecretStorage::Memory { values: stored } => {
+                let values = values
+                    .iter()
+                    .map(|(key, value)| {
+                        Ok((key.clone(), Zeroizing::new(serde_json::to_string(value)?)))
+                    })
+                    .collect::<Result<_, ConfigError>>()?;
+                // The replaced buffers are zeroed as they're dropped
+                *stored.lock().expect("secret values lock poisoned") = values;
+            }
+            SecretStorage::EnvOnly => return Err(ConfigError::EnvOnlyReadOnly),
+        };
+        Ok(())
+    }
+}
+
+/// A set of staged param changes to a [`Config`], applied together on commit.
+///
+/// Created by [`Config::begin_transaction`]. `set_param` and `delete` only
+/// buffer changes in memory; reads through the transaction see them layered
+/// over the stored values. [`Transaction::commit`] writes everything in a
+/// single save under the config lock, while [`Transaction::rollback`] or
+/// dropping the transaction discards the changes.
+pub struct Transaction<'a> {
+    config: &'a Config,
+    // A value of None stages a deletion
+    staged: HashMap<String, Option<Value>>,
+}
+
+impl Transaction<'_> {
+    /// Get a configuration value, including changes staged in this transaction.
+    ///
+    /// Environment variables still take precedence, as with [`Config::get_param`].
+    pub fn get_param<T: for<'de> Deserialize<'de>>(&self, key: &str) -> Result<T, ConfigError> {
+        if let Some(raw) = self.config.env_var(key) {
+            let hint = match self.staged.get(key) {
+                Some(staged) => staged.clone(),
+                None => self.config.param_hint(key),
+            };
+            return deserialize_value(key, coerce_env_value(&raw, hint.as_ref()));
+        }
+
+        match self.staged.get(key) {
+            Some(Some(value)) => deserialize_value(key, value.clone()),
+            Some(None) => Err(ConfigError::NotFound(key.to_string())),
+            None => self.config.get_param(key),
+        }
+    }
+
+    /// Stage setting a configuration value
+    pub fn set_param(&mut self, key: &str, value: Value) {
+        self.staged.insert(key.to_string(), Some(value));
+    }
+
+    /// Stage deleting a configuration value
+    pub fn delete(&mut self, key: &str) {
+        self.staged.insert(key.to_string(), None);
+    }
+
+    /// Write all staged changes in one atomic batch.
+    ///
+    /// Keys registered with [`Config::mark_secret`] are written to the secret
+    /// store instead, as with [`Config::set_param`]. The params and the
+    /// secrets are each written once under the config lock, as with
+    /// [`Config::set_many`].
+    ///
+    /// # Errors
+    ///
+    /// Returns a ConfigError if the config or the secret store can't be read
+    /// or written, in which case none of the changes are applied
+    pub fn commit(self) -> Result<(), ConfigError> {
+        let (secrets, params): (HashMap<_, _>, HashMap<_, _>) = self
+            .staged
+            .into_iter()
+            .partition(|(key, _)| self.config.is_marked_secret(key));
+
+        let lock = self.config.lock()?;
+        let mut values = self.config.load_values_locked(&lock)?;
+        for (key, value) in params {
+            match value {
+                Some(value) => values.insert(key, value),
+                None => values.remove(&key),
+            };
+        }
+        self.config.save_values_with_secrets(&lock, values, secrets)
+    }
+
+    /// Discard all staged changes
+    pub fn rollback(self) {
+        // Dropping the transaction discards the staged changes
+    }
+}
+
+/// A view of a [`Config`] that reads and writes params under a key prefix.
+///
+/// Created by [`Config::scoped`]. Keys passed to a scoped config are joined to
+/// the prefix with a separator (`_` unless changed with
+/// [`ScopedConfig::with_separator`]), so `config.scoped("openai")` reads
+/// `api_key` from the same slot as `config.get_param("openai_api_key")`,
+/// including the `OPENAI_API_KEY` environment override. Nothing about the
+/// stored layout changes.
+pub struct ScopedConfig<'a> {
+    config: &'a Config,
+    prefix: String,
+    separator: String,
+}
+
+impl ScopedConfig<'_> {
+    /// Join the prefix and `key` with `separator` instead of `_`
+    pub fn with_separator(mut self, separator: &str) -> Self {
+        self.separator = separator.to_string();
+        self
+    }
+
+    /// The full key a scoped key is stored under
+    pub fn key(&self, key: &str) -> String {
+        format!("{}{}{}", self.prefix, self.separator, key)
+    }
+
+    /// Get a param under the prefix, as with [`Config::get_param`].
+    pub fn get_param<T: for<'de> Deserialize<'de>>(&self, key: &str) -> Result<T, ConfigError> {
+        self.config.get_param(&self.key(key))
+    }
+
+    /// Set a param under the prefix, as with [`Config::set_param`].
+    pub fn set_param(&self, key: &str, value: Value) -> Result<(), ConfigError> {
+        self.config.set_param(&self.key(key), value)
+    }
+
+    /// Delete a param under the prefix, as with [`Config::delete`].
+    pub fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.config.delete(&self.key(key))
+    }
+
+    /// List the stored params under the prefix, sorted, with the prefix removed.
+    pub fn list(&self) -> Result<Vec<String>, ConfigError> {
+        let prefix = self.key("");
+        Ok(self
+            .config
+            .list_params()?
+            .into_iter()
+            .filter_map(|key| key.strip_prefix(&prefix).map(str::to_string))
+            .collect())
+    }
+}
+
+// Remove the version key from stored values, returning the version they were
+// saved with. Values saved before versioning count as version 0.
+fn take_version(values: &mut HashMap<String, Value>) -> u32 {
+    values
+        .remove(CONFIG_VERSION_KEY)
+        .and_then(|version| version.as_u64())
+        .map_or(0, |version| version as u32)
+}
+
+// Try each key in order, returning the first one that isn't NotFound
+fn first_found<T>(
+    keys: &[&str],
+    mut get: impl FnMut(&str) -> Result<T, ConfigError>,
+) -> Result<T, ConfigError> {
+    for key in keys {
+        match get(key) {
+            Err(ConfigError::NotFound(_)) => continue,
+            result => return result,
+        }
+    }
+    Err(ConfigError::NotFound(keys.join(", ")))
+}
+
+// Replace the file at `path` by writing a temporary file next to it and renaming
+// it over the target, so readers and crashes never see a partial write. The
+// existing file's permissions are kept.
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), ConfigError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| ConfigError::DirectoryError(e.to_string()))?;
+    }
+
+    let mut temp_path = path.as_os_str().to_os_string();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    let mut file = File::create(&temp_path)?;
+    if let Ok(metadata) = std::fs::metadata(path) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    std::fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+// The passphrase for the file secret store, if one is configured
+fn secrets_passphrase() -> Option<Zeroizing<String>> {
+    env::var(SECRETS_PASSPHRASE_ENV)
+        .ok()
+        .filter(|passphrase| !passphrase.is_empty())
+        .map(Zeroizing::new)
+}
+
+// Read the file secret store, which is either encrypted or legacy plaintext
+// YAML, treating a missing file as empty
+fn read_secrets_file(path: &Path) -> Result<HashMap<String, Value>, ConfigError> {
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+
+    let contents = Zeroizing::new(std::fs::read(path)?);
+    if !crypto::is_encrypted(&contents) {
+        return parse_values(&String::from_utf8_lossy(&contents), ConfigFormat::Yaml);
+    }
+
+    let passphrase = secrets_passphrase().ok_or_else(|| {
+        ConfigError::SecretsDecryptionError(format!(
+            "the secrets file is encrypted but {} is not set",
+            SECRETS_PASSPHRASE_ENV
+        ))
+    })?;
+    let plaintext = crypto::decrypt(&contents, &passphrase)?;
+    parse_values(&String::from_utf8_lossy(&plaintext), ConfigFormat::Yaml)
+}
+
+// Write the file secret store, encrypting it if a passphrase is configured
+fn write_secrets_file(path: &Path, values: &HashMap<String, Value>) -> Result<(), ConfigError> {
+    let yaml_value = Zeroizing::new(to_yaml_string(values)?);
+    match secrets_passphrase() {
+        Some(passphrase) => {
+            let encrypted = crypto::encrypt(yaml_value.as_bytes(), &passphrase)?;
+            write_atomically(path, &encrypted)
+        }
+        None => write_atomically(path, yaml_value.as_bytes()),
+    }
+}
+
+// Parse a YAML file into a map of values, treating a missing file as empty
+fn read_yaml_values(path: &Path) -> Result<HashMap<String, Value>, ConfigError> {
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+
+    let file_content = std::fs::read_to_string(path)?;
+    parse_values(&file_content, ConfigFormat::Yaml)
+}
+
+// Parse content into a map of values, treating a non-map document as empty
+fn parse_values(
+    content: &str,
+    format: ConfigFormat,
+) -> Result<HashMap<String, Value>, ConfigError> {
+    // Files edited on Windows may start with a BOM or use CRLF line endings
+    let content = normalize_text(content);
+    // Parse into JSON Value for consistent internal representation
+    let json_value: Value = match format {
+        ConfigFormat::Yaml => {
+            serde_json::to_value(serde_yaml::from_str::<serde_yaml::Value>(&content)?)?
+        }
+        ConfigFormat::Json if content.trim().is_empty() => Value::Null,
+        ConfigFormat::Json => serde_json::from_str(&content)?,
+        ConfigFormat::Toml => serde_json::to_value(toml::from_str::<toml::Value>(&content)?)?,
+    };
+
+    match json_value {
+        Value::Object(map) => Ok(map.into_iter().collect()),
+        _ => Ok(HashMap::new()),
+    }
+}
+
+// Serialize values to YAML with LF line endings
+fn to_yaml_string(values: &HashMap<String, Value>) -> Result<String, ConfigError> {
+    Ok(normalize_text(&serde_yaml::to_string(values)?))
+}
+
+// Strip a leading UTF-8 byte order mark and convert CRLF line endings to LF
+fn normalize_text(content: &str) -> String {
+    content
+        .strip_prefix('\u{feff}')
+        .unwrap_or(content)
+        .replace("\r\n", "\n")
+}
+
+// The value a secret had before rotate_secret
+#[derive(Serialize, Deserialize)]
+struct PreviousSecret {
+    value: Value,
+    expires_at: i64,
+}
+
+// Remove the previous values kept by rotate_secret from the stored secrets.
+// An unreadable entry counts as empty so it gets cleaned up on the next write.
+fn take_previous_secrets(values: &mut HashMap<String, Value>) -> HashMap<String, PreviousSecret> {
+    values
+        .remove(PREVIOUS_SECRETS_KEY)
+        .and_then(|previous| serde_json::from_value(previous).ok())
+        .unwrap_or_default()
+}
+
+fn put_previous_secrets(
+    values: &mut HashMap<String, Value>,
+    previous: HashMap<String, PreviousSecret>,
+) -> Result<(), ConfigError> {
+    if !previous.is_empty() {
+        values.insert(
+            PREVIOUS_SECRETS_KEY.to_string(),
+            serde_json::to_value(previous)?,
+        );
+    }
+    Ok(())
+}
+
+/// Deserialize the value of `key`, naming the key in the error if it fails.
+fn deserialize_value<T: for<'de> Deserialize<'de>>(
+    key: &str,
+    value: Value,
+) -> Result<T, ConfigError> {
+    serde_json::from_value(value).map_err(|err| match ConfigError::from(err) {
+        ConfigError::DeserializeError {
+            kind,
+            message,
+            source,
+            ..
+        } => ConfigError::DeserializeError {
+            key: Some(key.to_string()),
+            kind,
+            message,
+            source,
+        },
+        other => other,
+    })
+}
+
+/// Convert a raw environment variable into a value, shaped like `target_hint`.
+///
+/// The hint is the value the env var overrides, if any, so an override can't
+/// flip the type of a typed config entry:
+/// - a string stays a string, even if it looks like JSON (e.g. `true`)
+/// - an array takes a JSON array, or otherwise splits on commas, coercing
+///   each item like the first existing item
+/// - anything else, or no hint, is parsed as JSON when possible so structured
+///   values can be supplied, and otherwise treated as a plain string
+fn coerce_env_value(raw: &str, target_hint: Option<&Value>) -> Value {
+    match target_hint {
+        Some(Value::String(_)) => Value::String(raw.to_string()),
+        Some(Value::Array(items)) => match serde_json::from_str(raw) {
+            Ok(Value::Array(parsed)) => Value::Array(parsed),
+            _ if raw.trim().is_empty() => Value::Array(Vec::new()),
+            _ => Value::Array(
+                raw.split(',')
+                    .map(|item| coerce_env_value(item.trim(), items.first()))
+                    .collect(),
+            ),
+        },
+        _ => serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use tempfile::NamedTempFile;
+
+    /// An in-process keyring that can simulate an unreachable backend.
+    #[derive(Default)]
+    struct MockKeyring {
+        entries: std::sync::Mutex<HashMap<(String, String), String>>,
+        unavailable: bool,
+        // How many times set_password was called
+        writes: std::sync::atomic::AtomicUsize,
+    }
+
+    impl MockKeyring {
+        fn unavailable() -> Self {
+            MockKeyring {
+                unavailable: true,
+                ..Default::default()
+            }
+        }
+
+        fn check_available(&self) -> keyring::Result<()> {
+            if self.unavailable {
+                return Err(keyring::Error::NoStorageAccess(
+                    "mock keyring is locked".into(),
+                ));
+            }
+            Ok(())
+        }
+    }
+
+    impl KeyringBackend for MockKeyring {
+        fn get_password(&self, service: &str, username: &str) -> keyring::Result<String> {
+            self.check_available()?;
+            self.entries
+                .lock()
+                .unwrap()
+                .get(&(service.to_string(), username.to_string()))
+                .cloned()
+                .ok_or(keyring::Error::NoEntry)
+        }
+
+        fn set_password(
+            &self,
+            service: &str,
+            username: &str,
+            password: &str,
+        ) -> keyring::Result<()> {
+            self.check_available()?;
+            self.writes.fetch_add(1, Ordering::SeqCst);
+            self.entries.lock().unwrap().insert(
+                (service.to_string(), username.to_string()),
+                password.to_string(),
+            );
+            Ok(())
+        }
+
+        fn delete_password(&self, service: &str, username: &str) -> keyring::Result<()> {
+            self.check_available()?;
+            self.entries
+                .lock()
+                .unwrap()
+                .remove(&(service.to_string(), username.to_string()))
+                .map(|_| ())
+                .ok_or(keyring::Error::NoEntry)
+        }
+    }
+
+    fn cleanup_keyring() -> Result<(), ConfigError> {
+        let entry = Entry::new(TEST_KEYRING_SERVICE, KEYRING_USERNAME)?;
+        match entry.delete_credential() {
+            Ok(_) => Ok(()),
+            Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    #[test]
+    fn test_basic_config() -> Result<(), ConfigError> {
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?;
+
+        // Set a simple string value
+        config.set_param("test_key", Value::String("test_value".to_string()))?;
+
+        // Test simple string retrieval
+        let value: String = config.get_param("test_key")?;
+        assert_eq!(value, "test_value");
+
+        // Test with environment variable override
+        std::env::set_var("TEST_KEY", "env_value");
+        let value: String = config.get_param("test_key")?;
+        assert_eq!(value, "env_value");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_complex_type() -> Result<(), ConfigError> {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct TestStruct {
+            field1: String,
+            field2: i32,
+        }
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?;
+
+        // Set a complex value
+        config.set_param(
+            "complex_key",
+            serde_json::json!({
+                "field1": "hello",
+                "field2": 42
+            }),
+        )?;
+
+        let value: TestStruct = config.get_param("complex_key")?;
+        assert_eq!(value.field1, "hello");
+        assert_eq!(value.field2, 42);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_value() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::new(temp_file.path(), TEST_KEYRING_SERVICE).unwrap();
+
+        let result: Result<String, ConfigError> = config.get_param("nonexistent_key");
+        assert!(matches!(result, Err(ConfigError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_yaml_formatting() -> Result<(), ConfigError> {
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?;
+
+        config.set_param("key1", Value::String("value1".to_string()))?;
+        config.set_param("key2", Value::Number(42.into()))?;
+
+        // Read the file directly to check YAML formatting
+        let content = std::fs::read_to_string(temp_file.path())?;
+        assert!(content.contains("key1: value1"));
+        assert!(content.contains("key2: 42"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_value_management() -> Result<(), ConfigError> {
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?;
+
+        config.set_param("key", Value::String("value".to_string()))?;
+
+        let value: String = config.get_param("key")?;
+        assert_eq!(value, "value");
+
+        config.delete("key")?;
+
+        let result: Result<String, ConfigError> = config.get_param("key");
+        assert!(matches!(result, Err(ConfigError::NotFound(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_based_secrets_management() -> Result<(), ConfigError> {
+        let config_file = NamedTempFile::new().unwrap();
+        let secrets_file = NamedTempFile::new().unwrap();
+        let config = Config::new_with_file_secrets(config_file.path(), secrets_file.path())?;
+
+        config.set_secret("key", Value::String("value".to_string()))?;
+
+        let value: String = config.get_secret("key")?;
+        assert_eq!(value, "value");
+
+        config.delete_secret("key")?;
+
+        let result: Result<String, ConfigError> = config.get_secret("key");
+        assert!(matches!(result, Err(ConfigError::NotFound(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_secret_management() -> Result<(), ConfigError> {
+        cleanup_keyring()?;
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?;
+
+        // Test setting and getting a simple secret
+        config.set_secret("api_key", Value::String("secret123".to_string()))?;
+        let value: String = config.get_secret("api_key")?;
+        assert_eq!(value, "secret123");
+
+        // Test environment variable override
+        std::env::set_var("API_KEY", "env_secret");
+        let value: String = config.get_secret("api_key")?;
+        assert_eq!(value, "env_secret");
+        std::env::remove_var("API_KEY");
+
+        // Test deleting a secret
+        config.delete_secret("api_key")?;
+        let result: Result<String, ConfigError> = config.get_secret("api_key");
+        assert!(matches!(result, Err(ConfigError::NotFound(_))));
+
+        cleanup_keyring()?;
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_multiple_secrets() -> Result<(), ConfigError> {
+        cleanup_keyring()?;
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?;
+
+        // Set multiple secrets
+        config.set_secret("key1", Value::String("secret1".to_string()))?;
+        config.set_secret("key2", Value::String("secret2".to_string()))?;
+
+        // Verify both exist
+        let value1: String = config.get_secret("key1")?;
+        let value2: String = config.get_secret("key2")?;
+        assert_eq!(value1, "secret1");
+        assert_eq!(value2, "secret2");
+
+        // Delete one secret
+        config.delete_secret("key1")?;
+
+        // Verify key1 is gone but key2 remains
+        let result1: Result<String, ConfigError> = config.get_secret("key1");
+        let value2: String = config.get_secret("key2")?;
+        assert!(matches!(result1, Err(ConfigError::NotFound(_))));
+        assert_eq!(value2, "secret2");
+
+        cleanup_keyring()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_writes() -> Result<(), ConfigError> {
+        use std::sync::{Arc, Barrier, Mutex};
+        use std::thread;
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Arc::new(Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?);
+        let barrier = Arc::new(Barrier::new(3)); // For 3 concurrent threads
+        let values = Arc::new(Mutex::new(HashMap::new()));
+        let mut handles = vec![];
+
+        // Initialize with empty values
+        config.save_values(HashMap::new())?;
+
+        // Spawn 3 threads that will try to write simultaneously
+        for i in 0..3 {
+            let config = Arc::clone(&config);
+            let barrier = Arc::clone(&barrier);
+            let values = Arc::clone(&values);
+            let handle = thread::spawn(move || -> Result<(), ConfigError> {
+                // Wait for all threads to reach this point
+                barrier.wait();
+
+                // Get the lock and update values
+                let mut values = values.lock().unwrap();
+                values.insert(format!("key{}", i), Value::String(format!("value{}", i)));
+
+                // Write all values
+                config.save_values(values.clone())?;
+                Ok(())
+            });
+            handles.push(handle);
+        }
+
+        // Wait for all threads to complete
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+
+        // Verify all values were written correctly
+        let final_values = config.load_values()?;
+
+        // Print the final values for debugging
+        println!("Final values: {:?}", final_values);
+
+        assert_eq!(
+            final_values.len(),
+            3,
+            "Expected 3 values, got {}",
+            final_values.len()
+        );
+
+        for i in 0..3 {
+            let key = format!("key{}", i);
+            let value = format!("value{}", i);
+            assert!(
+                final_values.get(&key).is_some(),
+                "Missing key {} in final values",
+                key
+            );
+            assert_eq!(
+                final_values.get(&key).unwrap(),
+                &Value::String(value),
+                "Incorrect value for key {}",
+                key
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_extension_writes() -> Result<(), ConfigError> {
+        use std::sync::{Arc, Barrier};
+        use std::thread;
+        use std::time::Duration;
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Arc::new(Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?);
+        let barrier = Arc::new(Barrier::new(3)); // For 3 concurrent threads
+        let mut handles = vec![];
+
+        // Initialize with empty values
+        config.save_values(HashMap::new())?;
+
+        // Spawn 3 threads that will try to write extension configs simultaneously
+        for i in 0..3 {
+            let config = Arc::clone(&config);
+            let barrier = Arc::clone(&barrier);
+            let handle = thread::spawn(move || -> Result<(), ConfigError> {
+                // Wait for all threads to reach this point
+                barrier.wait();
+
+                // Add a small random delay to increase chance of concurrent access
+                thread::sleep(Duration::from_millis(i * 10));
+
+                let extension_key = format!("extension_{}", i);
+
+                // Use set_param which handles concurrent access properly
+                config.set_param(
+                    &extension_key,
+                    serde_json::json!({
+                        "name": format!("test_extension_{}", i),
+                        "version": format!("1.0.{}", i),
+                        "enabled": true,
+                        "settings": {
+                            "option1": format!("value{}", i),
+                            "option2": i
+                        }
+                    }),
+                )?;
+                Ok(())
+            });
+            handles.push(handle);
+        }
+
+        // Wait for all threads to complete
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+
+        // Verify all extension configs were written correctly
+        let final_values = config.load_values()?;
+
+        // Print the final values for debugging
+        println!("Final values: {:?}", final_values);
+
+        assert_eq!(
+            final_values.len(),
+            3,
+            "Expected 3 extension configs, got {}",
+            final_values.len()
+        );
+
+        for i in 0..3 {
+            let extension_key = format!("extension_{}", i);
+
+            let config = final_values
+                .get(&extension_key)
+                .expect(&format!("Missing extension config for {}", extension_key));
+
+            // Verify the structure matches what we wrote
+            let config_obj = config.as_object().unwrap();
+            assert_eq!(
+                config_obj.get("name").unwrap().as_str().unwrap(),
+                format!("test_extension_{}", i)
+            );
+            assert_eq!(
+                config_obj.get("version").unwrap().as_str().unwrap(),
+                format!("1.0.{}", i)
+            );
+            assert!(config_obj.get("enabled").unwrap().as_bool().unwrap());
+
+            let settings = config_obj.get("settings").unwrap().as_object().unwrap();
+            assert_eq!(
+                settings.get("option1").unwrap().as_str().unwrap(),
+                format!("value{}", i)
+            );
+            assert_eq!(settings.get("option2").unwrap().as_i64().unwrap() as i32, i);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_effective_config() -> Result<(), ConfigError> {
+        let config_file = NamedTempFile::new().unwrap();
+        let secrets_file = NamedTempFile::new().unwrap();
+        let config = Config::new_with_file_secrets(config_file.path(), secrets_file.path())?;
+
+        config.set_param("effective_model", Value::String("gpt-4o".to_string()))?;
+        config.set_param("effective_provider", Value::String("openai".to_string()))?;
+        config.set_secret("effective_api_key", Value::String("sk-secret".to_string()))?;
+
+        let effective = temp_env::with_var("EFFECTIVE_PROVIDER", Some("anthropic"), || {
+            config.effective_config()
+        })?;
+
+        assert_eq!(
+            effective,
+            serde_json::json!({
+                "effective_model": "gpt-4o",
+                "effective_provider": "anthropic",
+                "secrets": {
+                    "effective_api_key": "***"
+                }
+            })
+        );
+
+        // A param named like the secrets object is shadowed by it
+        config.set_param("secrets", Value::String("not a secret".to_string()))?;
+        assert_eq!(
+            config.effective_config()?["secrets"],
+            serde_json::json!({"effective_api_key": "***"})
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_or_set_with_runs_init_once() -> Result<(), ConfigError> {
+        use std::cell::Cell;
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?;
+        let calls = Cell::new(0);
+        let init = || {
+            calls.set(calls.get() + 1);
+            format!("install-{}", calls.get())
+        };
+
+        let first: String = config.get_or_set_with("install_id", init)?;
+        let second: String = config.get_or_set_with("install_id", init)?;
+        assert_eq!(first, "install-1");
+        assert_eq!(second, first);
+        assert_eq!(calls.get(), 1);
+
+        // The generated value is persisted, so another instance sees it too
+        let other = Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?;
+        let persisted: String = other.get_or_set_with("install_id", init)?;
+        assert_eq!(persisted, first);
+        assert_eq!(calls.get(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_or_set_with_serves_defaults() -> Result<(), ConfigError> {
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?.with_defaults(
+            HashMap::from([("default_model".to_string(), Value::String("gpt-4o".into()))]),
+        );
+
+        let model: String = config.get_or_set_with("default_model", || -> String {
+            panic!("init must not run for a defaulted key")
+        })?;
+        assert_eq!(model, "gpt-4o");
+        // The default is served, not written to the file
+        assert!(config.list_params()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_secret_not_found_with_reachable_keyring() -> Result<(), ConfigError> {
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::new_with_keyring_backend(
+            temp_file.path(),
+            TEST_KEYRING_SERVICE,
+            Arc::new(MockKeyring::default()),
+        )?;
+
+        let result: Result<String, ConfigError> = config.get_secret("missing_secret");
+        assert!(matches!(result, Err(ConfigError::NotFound(_))));
+
+        config.set_secret("present_secret", Value::String("value".to_string()))?;
+        let result: Result<String, ConfigError> = config.get_secret("missing_secret");
+        assert!(matches!(result, Err(ConfigError::NotFound(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_secret_backend_unavailable() -> Result<(), ConfigError> {
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::new_with_keyring_backend(
+            temp_file.path(),
+            TEST_KEYRING_SERVICE,
+            Arc::new(MockKeyring::unavailable()),
+        )?;
+
+        let result: Result<String, ConfigError> = config.get_secret("missing_secret");
+        assert!(matches!(
+            result,
+            Err(ConfigError::SecretBackendUnavailable(_))
+        ));
+
+        let result = config.set_secret("key", Value::String("value".to_string()));
+        assert!(matches!(
+            result,
+            Err(ConfigError::SecretBackendUnavailable(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_marked_secret_routes_to_secret_store() -> Result<(), ConfigError> {
+        let config_file = NamedTempFile::new().unwrap();
+        let secrets_file = NamedTempFile::new().unwrap();
+        let config = Config::new_with_file_secrets(config_file.path(), secrets_file.path())?;
+
+        config.mark_secret("marked_api_key");
+        assert!(config.is_marked_secret("marked_api_key"));
+        assert!(!config.is_marked_secret("marked_model"));
+
+        config.set("marked_api_key", Value::String("sk-123".into()), false)?;
+        config.set("marked_model", Value::String("gpt-4o".into()), false)?;
+
+        assert!(!config.load_values()?.contains_key("marked_api_key"));
+        assert!(config.load_values()?.contains_key("marked_model"));
+        assert_eq!(
+            config.load_secrets()?.get("marked_api_key"),
+            Some(&Value::String("sk-123".into()))
+        );
+        assert_eq!(
+            config.get("marked_api_key", false)?,
+            Value::String("sk-123".into())
+        );
+
+        // set_param on a marked key is redirected too
+        config.set_param("marked_api_key", Value::String("sk-456".into()))?;
+        assert!(!config.load_values()?.contains_key("marked_api_key"));
+        let value: String = config.get_secret("marked_api_key")?;
+        assert_eq!(value, "sk-456");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_clone_to_persists_in_memory_config() -> Result<(), ConfigError> {
+        let memory = Config::new_in_memory();
+        memory.set_param("clone_provider", Value::String("openai".into()))?;
+        memory.set_param("clone_settings", serde_json::json!({"temperature": 0.5}))?;
+        memory.set_secret("clone_api_key", Value::String("sk-123".into()))?;
+        assert_eq!(memory.path(), "<in-memory>");
+
+        let config_file = NamedTempFile::new().unwrap();
+        let secrets_file = NamedTempFile::new().unwrap();
+        let dest = Config::new_with_file_secrets(config_file.path(), secrets_file.path())?;
+        dest.set_param("existing_key", Value::Bool(true))?;
+
+        memory.clone_to(&dest)?;
+
+        let provider: String = dest.get_param("clone_provider")?;
+        assert_eq!(provider, "openai");
+        assert_eq!(
+            dest.get_param::<Value>("clone_settings")?,
+            serde_json::json!({"temperature": 0.5})
+        );
+        let api_key: String = dest.get_secret("clone_api_key")?;
+        assert_eq!(api_key, "sk-123");
+        assert!(dest.get_param::<bool>("existing_key")?);
+
+        // The data really is on disk, not just cached in the destination
+        let reopened = Config::new_with_file_secrets(config_file.path(), secrets_file.path())?;
+        assert_eq!(reopened.load_values()?.len(), 3);
+        assert_eq!(reopened.load_secrets()?.len(), 1);
+
+        // All secrets are copied in one write
+        memory.set_secret("clone_other_key", Value::String("sk-456".into()))?;
+        let keyring = Arc::new(MockKeyring::default());
+        let keyring_dest = Config::new_with_keyring_backend(
+            config_file.path(),
+            TEST_KEYRING_SERVICE,
+            keyring.clone(),
+        )?;
+        memory.clone_to(&keyring_dest)?;
+        assert_eq!(keyring.writes.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            keyring_dest.list_secret_keys()?,
+            vec!["clone_api_key", "clone_other_key"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_keyring_accounts_are_isolated() -> Result<(), ConfigError> {
+        let keyring = Arc::new(MockKeyring::default());
+        let temp_file = NamedTempFile::new().unwrap();
+
+        let work = Config::new_with_keyring_backend(
+            temp_file.path(),
+            TEST_KEYRING_SERVICE,
+            keyring.clone(),
+        )?
+        .with_keyring_account("work");
+        let personal = Config::new_with_keyring_backend(
+            temp_file.path(),
+            TEST_KEYRING_SERVICE,
+            keyring.clone(),
+        )?
+        .with_keyring_account("personal");
+        let default = Config::new_with_keyring_backend(
+            temp_file.path(),
+            TEST_KEYRING_SERVICE,
+            keyring.clone(),
+        )?;
+
+        work.set_secret("api_key", Value::String("work-key".into()))?;
+        personal.set_secret("api_key", Value::String("personal-key".into()))?;
+
+        assert_eq!(work.get_secret::<String>("api_key")?, "work-key");
+        assert_eq!(personal.get_secret::<String>("api_key")?, "personal-key");
+        assert!(matches!(
+            default.get_secret::<String>("api_key"),
+            Err(ConfigError::NotFound(_))
+        ));
+
+        let entries = keyring.entries.lock().unwrap();
+        assert!(entries.contains_key(&(TEST_KEYRING_SERVICE.to_string(), "work".to_string())));
+        assert!(entries.contains_key(&(TEST_KEYRING_SERVICE.to_string(), "personal".to_string())));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_sources_are_preserved() -> Result<(), ConfigError> {
+        use std::error::Error as _;
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::new_with_keyring_backend(
+            temp_file.path(),
+            TEST_KEYRING_SERVICE,
+            Arc::new(MockKeyring::unavailable()),
+        )?;
+
+        config.set_param("source_port", Value::String("not a number".into()))?;
+        let err = config.get_param::<u16>("source_port").unwrap_err();
+        assert!(matches!(err, ConfigError::DeserializeError { .. }));
+        assert!(err
+            .source()
+            .and_then(|source| source.downcast_ref::<serde_json::Error>())
+            .is_some());
+
+        let err = config.get_secret::<String>("source_secret").unwrap_err();
+        assert!(matches!(err, ConfigError::SecretBackendUnavailable(_)));
+        let source = err
+            .source()
+            .and_then(|source| source.downcast_ref::<keyring::Error>());
+        assert!(matches!(source, Some(keyring::Error::NoStorageAccess(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dotenv_overrides() -> Result<(), ConfigError> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let dotenv_path = dir.path().join(".env");
+        let config = Config::new_with_file_secrets(
+            dir.path().join("config.yaml"),
+            dir.path().join("secrets.yaml"),
+        )?;
+        config.set_param("dotenv_test_model", Value::String("from-file".into()))?;
+        config.set_param("dotenv_test_provider", Value::String("from-file".into()))?;
+        config.set_secret("dotenv_test_api_key", Value::String("from-store".into()))?;
+
+        std::fs::write(
+            &dotenv_path,
+            "DOTENV_TEST_MODEL=from-dotenv\nDOTENV_TEST_PROVIDER=from-dotenv\n\
+             DOTENV_TEST_API_KEY=sk-dotenv\nDOTENV_TEST_PORT=8080\n",
+        )?;
+        config.load_dotenv(Some(&dotenv_path))?;
+
+        temp_env::with_var("DOTENV_TEST_PROVIDER", Some("from-env"), || {
+            // A real env var beats the dotenv override, which beats the file
+            assert_eq!(
+                config.get_param::<String>("dotenv_test_model").unwrap(),
+                "from-dotenv"
+            );
+            assert_eq!(
+                config.get_param::<String>("dotenv_test_provider").unwrap(),
+                "from-env"
+            );
+            assert_eq!(config.get_param::<u16>("dotenv_test_port").unwrap(), 8080);
+            assert_eq!(
+                config.get_param_source("dotenv_test_model").unwrap(),
+                ValueSource::Env
+            );
+            assert_eq!(
+                config.get_secret::<String>("dotenv_test_api_key").unwrap(),
+                "sk-dotenv"
+            );
+            assert!(std::env::var("DOTENV_TEST_MODEL").is_err());
+        });
+
+        // Reloading replaces the overrides instead of adding to them
+        std::fs::write(&dotenv_path, "DOTENV_TEST_API_KEY=sk-rotated\n")?;
+        config.load_dotenv(Some(&dotenv_path))?;
+        assert_eq!(
+            config.get_param::<String>("dotenv_test_model")?,
+            "from-file"
+        );
+        assert!(!config.contains_param("dotenv_test_port"));
+        assert_eq!(
+            config.get_secret::<String>("dotenv_test_api_key")?,
+            "sk-rotated"
+        );
+
+        // A file that can't be read keeps the current overrides
+        assert!(config
+            .load_dotenv(Some(&dir.path().join("missing.env")))
+            .is_err());
+        assert_eq!(
+            config.get_secret::<String>("dotenv_test_api_key")?,
+            "sk-rotated"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_errors_name_the_key() -> Result<(), ConfigError> {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct ServerConfig {
+            host: String,
+            port: u16,
+        }
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::new_with_keyring_backend(
+            temp_file.path(),
+            TEST_KEYRING_SERVICE,
+            Arc::new(MockKeyring::default()),
+        )?;
+        config.set_param(
+            "server",
+            serde_json::json!({"host": "localhost", "port": "eighty"}),
+        )?;
+
+        let err = config.get_param::<ServerConfig>("server").unwrap_err();
+        assert_eq!(err.failed_key(), Some("server"));
+        assert_eq!(err.deserialize_kind(), Some(DeserializeKind::TypeMismatch));
+        assert!(err.to_string().contains("`server`"));
+        assert!(err.to_string().contains("expected u16"));
+
+        let scoped = config.scoped("server");
+        scoped.set_param("port", Value::String("eighty".into()))?;
+        let err = scoped.get_param::<u16>("port").unwrap_err();
+        assert_eq!(err.failed_key(), Some("server_port"));
+
+        config.set_secret("api_key", serde_json::json!(["not", "a", "string"]))?;
+        let err = config.get_secret::<String>("api_key").unwrap_err();
+        assert_eq!(err.failed_key(), Some("api_key"));
+
+        // A file that can't be parsed has no single key to blame
+        std::fs::write(temp_file.path(), "server: [unclosed")?;
+        let err = config.get_param::<ServerConfig>("server").unwrap_err();
+        assert_eq!(err.failed_key(), None);
+        assert_eq!(err.deserialize_kind(), Some(DeserializeKind::Syntax));
+        assert!(err.to_string().starts_with("Failed to deserialize value: "));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_watch_values_debounces_rapid_writes() -> Result<(), ConfigError> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        let config = Config::new_with_file_secrets(&config_path, dir.path().join("secrets.yaml"))?;
+        config.set_param("model", Value::String("initial".into()))?;
+
+        let debounce = Duration::from_millis(300);
+        let (tx, delivered) = mpsc::channel();
+        let handle = config.watch_values(debounce, move |values| {
+            let _ = tx.send(values);
+        })?;
+
+        // An editor saving in several steps, including a transiently empty file
+        std::fs::write(&config_path, "")?;
+        std::fs::write(&config_path, "model: partial\n")?;
+        std::fs::write(&config_path, "")?;
+        std::fs::write(&config_path, "model: final\n")?;
+        let values = delivered
+            .recv_timeout(Duration::from_secs(5))
+            .expect("no callback for the final content");
+        assert_eq!(values.get("model"), Some(&Value::String("final".into())));
+
+        // Rewriting identical content doesn't produce another callback
+        std::fs::write(&config_path, "model: final\n")?;
+        let extra = delivered.recv_timeout(debounce * 3);
+        assert!(
+            extra.is_err(),
+            "expected one callback, also got {:?}",
+            extra
+        );
+        handle.stop();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted_file_secrets() -> Result<(), ConfigError> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let secrets_path = dir.path().join("secrets.yaml");
+        let config = Config::new_with_file_secrets(dir.path().join("config.yaml"), &secrets_path)?;
+
+        // Legacy plaintext secrets are still readable, and are encrypted on the next write
+        std::fs::write(&secrets_path, "legacy_key: legacy-value\n")?;
+        temp_env::with_var(SECRETS_PASSPHRASE_ENV, Some("correct horse"), || {
+            assert_eq!(
+                config.get_secret::<String>("legacy_key").unwrap(),
+                "legacy-value"
+            );
+            config
+                .set_secret("api_key", Value::String("sk-12345".into()))
+                .unwrap();
+        });
+
+        let contents = std::fs::read(&secrets_path)?;
+        assert!(crypto::is_encrypted(&contents));
+        assert!(!String::from_utf8_lossy(&contents).contains("sk-12345"));
+
+        temp_env::with_var(SECRETS_PASSPHRASE_ENV, Some("correct horse"), || {
+            assert_eq!(config.get_secret::<String>("api_key").unwrap(), "sk-12345");
+            assert_eq!(
+                config.get_secret::<String>("legacy_key").unwrap(),
+                "legacy-value"
+            );
+        });
+
+        // A wrong or missing passphrase is reported as such, not as a missing secret
+        temp_env::with_var(SECRETS_PASSPHRASE_ENV, Some("wrong"), || {
+            assert!(matches!(
+                config.get_secret::<String>("api_key"),
+                Err(ConfigError::SecretsDecryptionError(_))
+            ));
+        });
+        temp_env::with_var_unset(SECRETS_PASSPHRASE_ENV, || {
+            assert!(matches!(
+                config.get_secret::<String>("api_key"),
+                Err(ConfigError::SecretsDecryptionError(_))
+            ));
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_setters_keep_every_key() -> Result<(), ConfigError> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        let secrets_path = dir.path().join("secrets.yaml");
+        let config = Arc::new(Config::new_with_file_secrets(&config_path, &secrets_path)?);
+
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let config = config.clone();
+                std::thread::spawn(move || -> Result<(), ConfigError> {
+                    config.set_param(&format!("key_{}", i), Value::from(i))?;
+                    config.set_secret(&format!("secret_{}", i), Value::from(i))
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+
+        // A second instance stands in for another process sharing the files
+        let reader = Config::new_with_file_secrets(&config_path, &secrets_path)?;
+        for i in 0..8 {
+            assert_eq!(reader.get_param::<i32>(&format!("key_{}", i))?, i);
+            assert_eq!(reader.get_secret::<i32>(&format!("secret_{}", i))?, i);
+        }
+        assert!(!dir.path().join("config.yaml.tmp").exists());
+        assert!(!dir.path().join("secrets.yaml.tmp").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_watch_reports_changed_keys() -> Result<(), ConfigError> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        let config = Config::new_with_file_secrets(&config_path, dir.path().join("secrets.yaml"))?;
+        config.set_param("model", Value::String("initial".into()))?;
+        config.set_param("theme", Value::String("dark".into()))?;
+
+        let changes = config.watch()?;
+        std::thread::sleep(Duration::from_millis(100));
+        let next = || changes.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        config.set_param("model", Value::String("updated".into()))?;
+        assert_eq!(
+            next(),
+            ConfigChange {
+                key: "model".to_string(),
+                old: Some(Value::String("initial".into())),
+                new: Some(Value::String("updated".into())),
+            }
+        );
+
+        // An editor replacing the file by renaming a temporary file over it
+        let temp_path = dir.path().join("config.yaml.tmp");
+        std::fs::write(&temp_path, "model: updated\nverbose: true\n")?;
+        std::fs::rename(&temp_path, &config_path)?;
+        assert_eq!(
+            next(),
+            ConfigChange {
+                key: "theme".to_string(),
+                old: Some(Value::String("dark".into())),
+                new: None,
+            }
+        );
+        assert_eq!(
+            next(),
+            ConfigChange {
+                key: "verbose".to_string(),
+                old: None,
+                new: Some(Value::Bool(true)),
+            }
+        );
+
+        // The watch is still attached after the rename
+        std::fs::write(&config_path, "model: renamed\nverbose: true\n")?;
+        assert_eq!(next().new, Some(Value::String("renamed".into())));
+        assert_eq!(config.get_param::<String>("model")?, "renamed");
+
+        let memory = Config::from_reader("model: fixed".as_bytes(), ConfigFormat::Yaml)?;
+        assert!(memory.watch()?.recv().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_values_cache_sees_external_writes() -> Result<(), ConfigError> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        let config = Config::new_with_file_secrets(&config_path, dir.path().join("secrets.yaml"))?;
+        config.set_param("model", Value::String("initial".into()))?;
+        assert_eq!(config.get_param::<String>("model")?, "initial");
+        assert_eq!(config.get_param::<String>("model")?, "initial");
+
+        std::fs::write(&config_path, "model: changed-externally\n")?;
+        assert_eq!(config.get_param::<String>("model")?, "changed-externally");
+
+        config.set_param("model", Value::String("set".into()))?;
+        assert_eq!(config.get_param::<String>("model")?, "set");
+
+        std::fs::remove_file(&config_path)?;
+        assert!(matches!(
+            config.get_param::<String>("model"),
+            Err(ConfigError::NotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_watch_key_ignores_unrelated_keys() -> Result<(), ConfigError> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        let config = Config::new_with_file_secrets(&config_path, dir.path().join("secrets.yaml"))?;
+        config.set_param("model", Value::String("initial".into()))?;
+        config.set_param("theme", Value::String("dark".into()))?;
+
+        let (tx, delivered) = mpsc::channel();
+        let handle = config.watch_key("model", move |value| {
+            let _ = tx.send(value);
+        })?;
+        let next = || delivered.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        config.set_param("model", Value::String("updated".into()))?;
+        assert_eq!(next(), Some(Value::String("updated".into())));
+
+        // Once the watch is known to deliver, an unrelated write produces nothing
+        config.set_param("theme", Value::String("light".into()))?;
+        let extra = delivered.recv_timeout(DEFAULT_WATCH_DEBOUNCE * 3);
+        assert!(extra.is_err(), "unexpected callback {:?}", extra);
+
+        config.delete("model")?;
+        assert_eq!(next(), None);
+        handle.stop();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_secret_backend_available() -> Result<(), ConfigError> {
+        let temp_file = NamedTempFile::new().unwrap();
+
+        let locked = Config::new_with_keyring_backend(
+            temp_file.path(),
+            TEST_KEYRING_SERVICE,
+            Arc::new(MockKeyring::unavailable()),
+        )?;
+        assert!(!locked.secret_backend_available());
+
+        // An empty but reachable keyring is available
+        let reachable = Config::new_with_keyring_backend(
+            temp_file.path(),
+            TEST_KEYRING_SERVICE,
+            Arc::new(MockKeyring::default()),
+        )?;
+        assert!(reachable.secret_backend_available());
+
+        let secrets_file = NamedTempFile::new().unwrap();
+        let file = Config::new_with_file_secrets(temp_file.path(), secrets_file.path())?;
+        assert!(file.secret_backend_available());
+        assert!(Config::new_in_memory().secret_backend_available());
+
+        // A fallback file doesn't hide the keyring being down, and probing
+        // doesn't switch to it
+        let fallback_path = secrets_file.path().with_extension("fallback");
+        let with_fallback = Config::new_with_keyring_backend(
+            temp_file.path(),
+            TEST_KEYRING_SERVICE,
+            Arc::new(MockKeyring::unavailable()),
+        )?
+        .with_keyring_fallback(&fallback_path);
+        assert!(!with_fallback.secret_backend_available());
+        assert!(with_fallback.fallback_path().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_keyring_falls_back_to_file() -> Result<(), ConfigError> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        let fallback_path = dir.path().join("secrets.yaml");
+
+        let config = Config::new_with_keyring_backend(
+            &config_path,
+            TEST_KEYRING_SERVICE,
+            Arc::new(MockKeyring::unavailable()),
+        )?
+        .with_keyring_fallback(&fallback_path);
+        assert_eq!(
+            config.secret_backend(),
+            SecretBackend::File(fallback_path.clone())
+        );
+        assert!(!config.secret_backend_available());
+
+        config.set_secret("api_key", Value::String("secret".into()))?;
+        assert_eq!(config.get_secret::<String>("api_key")?, "secret");
+        assert!(fallback_path.exists());
+
+        // A reachable keyring is used as usual and the fallback file is left alone
+        let reachable_fallback = dir.path().join("unused-secrets.yaml");
+        let reachable = Config::new_with_keyring_backend(
+            &config_path,
+            TEST_KEYRING_SERVICE,
+            Arc::new(MockKeyring::default()),
+        )?
+        .with_keyring_fallback(&reachable_fallback);
+        reachable.set_secret("api_key", Value::String("secret".into()))?;
+        assert_eq!(reachable.secret_backend(), SecretBackend::Keyring);
+        assert!(!reachable_fallback.exists());
+
+        // Without a fallback an unusable keyring is still an error
+        let strict = Config::new_with_keyring_backend(
+            &config_path,
+            TEST_KEYRING_SERVICE,
+            Arc::new(MockKeyring::unavailable()),
+        )?;
+        assert_eq!(strict.secret_backend(), SecretBackend::Keyring);
+        assert!(matches!(
+            strict.set_secret("api_key", Value::String("secret".into())),
+            Err(ConfigError::SecretBackendUnavailable(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_coerce_env_value() {
+        // Without a hint, JSON is parsed and anything else is a string
+        assert_eq!(coerce_env_value("true", None), Value::Bool(true));
+        assert_eq!(
+            coerce_env_value(r#"{"port":8080}"#, None),
+            serde_json::json!({"port": 8080})
+        );
+        assert_eq!(
+            coerce_env_value("a,b,c", None),
+            Value::String("a,b,c".into())
+        );
+
+        // A string that looks like a bool or JSON stays a string
+        let string = Value::String("yes".into());
+        assert_eq!(
+            coerce_env_value("true", Some(&string)),
+            Value::String("true".into())
+        );
+        assert_eq!(
+            coerce_env_value("[1, 2]", Some(&string)),
+            Value::String("[1, 2]".into())
+        );
+
+        // A comma list becomes an array shaped like the existing items
+        let hosts = serde_json::json!(["localhost"]);
+        assert_eq!(
+            coerce_env_value("a, b,c", Some(&hosts)),
+            serde_json::json!(["a", "b", "c"])
+        );
+        assert_eq!(
+            coerce_env_value(r#"["x","y"]"#, Some(&hosts)),
+            serde_json::json!(["x", "y"])
+        );
+        assert_eq!(coerce_env_value("", Some(&hosts)), serde_json::json!([]));
+        let ports = serde_json::json!([80]);
+        assert_eq!(
+            coerce_env_value("8080,8443", Some(&ports)),
+            serde_json::json!([8080, 8443])
+        );
+
+        // Numbers and bools parse as before
+        assert_eq!(
+            coerce_env_value("42", Some(&Value::from(1))),
+            Value::from(42)
+        );
+        assert_eq!(
+            coerce_env_value("false", Some(&Value::Bool(true))),
+            Value::Bool(false)
+        );
+    }
+
+    #[test]
+    fn test_env_override_keeps_stored_shape() -> Result<(), ConfigError> {
+        let config = Config::new_in_memory();
+        config.set_param("shape_flag", Value::String("on".into()))?;
+        config.set_param("shape_hosts", serde_json::json!(["localhost"]))?;
+
+        temp_env::with_vars(
+            [("SHAPE_FLAG", Some("true")), ("SHAPE_HOSTS", Some("a,b,c"))],
+            || {
+                assert_eq!(config.get_param::<String>("shape_flag").unwrap(), "true");
+                assert_eq!(
+                    config.get_param::<Vec<String>>("shape_hosts").unwrap(),
+                    vec!["a", "b", "c"]
+                );
+            },
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_and_import() -> Result<(), ConfigError> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = Config::new_with_file_secrets(
+            dir.path().join("config.yaml"),
+            dir.path().join("secrets.yaml"),
+        )?;
+        source.set_param("model", Value::String("gpt-4o".into()))?;
+        source.set_param("hosts", serde_json::json!(["a", "b"]))?;
+        source.set_secret("api_key", Value::String("sk-12345".into()))?;
+
+        // Secrets are redacted by default
+        let redacted = source.export(ExportOptions::default())?;
+        assert!(!redacted.contains("sk-12345"));
+        let snapshot: Value = serde_json::from_str(&redacted)?;
+        assert_eq!(snapshot["params"]["model"], "gpt-4o");
+        assert_eq!(snapshot["secrets"]["api_key"], "***");
+
+        let full = source.export(ExportOptions {
+            include_secrets: true,
+        })?;
+        assert!(full.contains("sk-12345"));
+
+        // Merging keeps unrelated keys, and redacted secrets don't clobber real ones
+        let merged = Config::new_in_memory();
+        merged.set_param("theme", Value::String("dark".into()))?;
+        merged.set_secret("api_key", Value::String("sk-mine".into()))?;
+        merged.import(&redacted, true)?;
+        assert_eq!(merged.get_param::<String>("model")?, "gpt-4o");
+        assert_eq!(merged.get_param::<String>("theme")?, "dark");
+        assert_eq!(merged.get_secret::<String>("api_key")?, "sk-mine");
+
+        // Replacing drops everything not in the export
+        let replaced = Config::new_in_memory();
+        replaced.set_param("theme", Value::String("dark".into()))?;
+        replaced.set_secret("other_key", Value::String("sk-other".into()))?;
+        replaced.import(&full, false)?;
+        assert_eq!(replaced.list_params()?, vec!["hosts", "model"]);
+        assert_eq!(replaced.list_secret_keys()?, vec!["api_key"]);
+        assert_eq!(replaced.get_secret::<String>("api_key")?, "sk-12345");
+
+        // A redacted export replaces params but leaves secrets alone
+        replaced.set_secret("other_key", Value::String("sk-other".into()))?;
+        replaced.import(&redacted, false)?;
+        assert_eq!(replaced.get_secret::<String>("other_key")?, "sk-other");
+        assert_eq!(replaced.get_secret::<String>("api_key")?, "sk-12345");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scoped_config() -> Result<(), ConfigError> {
+        let config = Config::new_in_memory();
+        config.set_param("scoped_openai_api_key", Value::String("sk-1".into()))?;
+        config.set_param("scoped_openai_model", Value::String("gpt-4o".into()))?;
+        config.set_param("scoped_anthropic_model", Value::String("claude".into()))?;
+
+        let openai = config.scoped("scoped_openai");
+        assert_eq!(openai.get_param::<String>("api_key")?, "sk-1");
+        assert_eq!(openai.list()?, vec!["api_key", "model"]);
+
+        openai.set_param("host", Value::String("localhost".into()))?;
+        assert_eq!(
+            config.get_param::<String>("scoped_openai_host")?,
+            "localhost"
+        );
+        openai.delete("api_key")?;
+        assert!(!config.contains_param("scoped_openai_api_key"));
+        assert_eq!(openai.list()?, vec!["host", "model"]);
+
+        // Environment overrides use the full uppercase key
+        temp_env::with_var("SCOPED_OPENAI_MODEL", Some("o1"), || {
+            assert_eq!(openai.get_param::<String>("model").unwrap(), "o1");
+        });
+
+        let dotted = config.scoped("scoped").with_separator(".");
+        dotted.set_param("nested", Value::Bool(true))?;
+        assert!(config.get_param::<bool>("scoped.nested")?);
+        assert_eq!(dotted.list()?, vec!["nested"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_and_delete_many() -> Result<(), ConfigError> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        let config = Config::new_with_keyring_backend(
+            &config_path,
+            TEST_KEYRING_SERVICE,
+            Arc::new(MockKeyring::default()),
+        )?;
+        config.set_param("theme", Value::String("dark".into()))?;
+        config.mark_secret("openai_api_key");
+
+        config.set_many(HashMap::from([
+            ("model".to_string(), Value::String("gpt-4o".into())),
+            ("provider".to_string(), Value::String("openai".into())),
+            ("openai_api_key".to_string(), Value::String("sk-1".into())),
+        ]))?;
+        assert_eq!(config.list_params()?, vec!["model", "provider", "theme"]);
+        assert_eq!(config.get_secret::<String>("openai_api_key")?, "sk-1");
+
+        config.delete_many(&["model".to_string(), "provider".to_string()])?;
+        assert_eq!(config.list_params()?, vec!["theme"]);
+
+        config.set_secrets_many(HashMap::from([
+            (
+                "anthropic_api_key".to_string(),
+                Value::String("sk-2".into()),
+            ),
+            ("google_api_key".to_string(), Value::String("sk-3".into())),
+        ]))?;
+        assert_eq!(
+            config.list_secret_keys()?,
+            vec!["anthropic_api_key", "google_api_key", "openai_api_key"]
+        );
+
+        // A batch whose secrets can't be stored writes none of its params
+        let read_only = Config::new_with_env_only_secrets(&config_path);
+        read_only.mark_secret("openai_api_key");
+        assert!(matches!(
+            read_only.set_many(HashMap::from([
+                ("model".to_string(), Value::String("gpt-4o".into())),
+                ("openai_api_key".to_string(), Value::String("sk-4".into())),
+            ])),
+            Err(ConfigError::EnvOnlyReadOnly)
+        ));
+        assert_eq!(read_only.list_params()?, vec!["theme"]);
+
+        // Secrets written before the config failed to save are rolled back
+        let blocker = dir.path().join("config.yaml.tmp");
+        std::fs::create_dir(&blocker)?;
+        assert!(config
+            .set_many(HashMap::from([
+                ("model".to_string(), Value::String("gpt-4o".into())),
+                ("openai_api_key".to_string(), Value::String("sk-5".into())),
+            ]))
+            .is_err());
+        std::fs::remove_dir(&blocker)?;
+        assert_eq!(config.list_params()?, vec!["theme"]);
+        assert_eq!(config.get_secret::<String>("openai_api_key")?, "sk-1");
+
+        // A failed write leaves everything as it was
+        assert!(read_only
+            .set_secrets_many(HashMap::from([(
+                "api_key".to_string(),
+                Value::String("sk".into())
+            )]))
+            .is_err());
+        assert!(read_only.list_secret_keys()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_param_or_and_contains() -> Result<(), ConfigError> {
+        let config = Config::new_in_memory();
+        config.set_param("or_port", Value::from(8080))?;
+        config.set_param("or_typo_port", Value::String("80 80".into()))?;
+        config.set_secret("or_api_key", Value::String("sk-1".into()))?;
+
+        assert_eq!(config.get_param_or("or_port", 3000)?, 8080);
+        assert_eq!(config.get_param_or("or_missing_port", 3000)?, 3000);
+        // A value of the wrong type is an error, not the default
+        assert!(matches!(
+            config.get_param_or("or_typo_port", 3000),
+            Err(ConfigError::DeserializeError { .. })
+        ));
+
+        assert!(config.contains_param("or_typo_port"));
+        assert!(!config.contains_param("or_missing_port"));
+        assert!(!config.contains_param("or_api_key"));
+        assert!(config.contains_secret("or_api_key"));
+        assert!(!config.contains_secret("or_port"));
+
+        temp_env::with_vars(
+            [
+                ("OR_ENV_ONLY", Some("1")),
+                ("OR_ENV_SECRET", Some("sk-env")),
+            ],
+            || {
+                assert!(config.contains_param("or_env_only"));
+                assert!(config.contains_secret("or_env_secret"));
+            },
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_any_falls_back_across_keys() -> Result<(), ConfigError> {
+        let config = Config::new_in_memory();
+        config.set_param("legacy_host", Value::String("localhost".into()))?;
+        config.set_secret("legacy_token", Value::String("secret".into()))?;
+
+        let host: String = config.get_param_any(&["any_new_host", "legacy_host"])?;
+        assert_eq!(host, "localhost");
+        let token: String = config.get_secret_any(&["any_new_token", "legacy_token"])?;
+        assert_eq!(token, "secret");
+
+        // Environment variables are checked for each candidate
+        temp_env::with_var("ANY_NEW_HOST", Some("example.com"), || {
+            let host: String = config
+                .get_param_any(&["any_new_host", "legacy_host"])
+                .unwrap();
+            assert_eq!(host, "example.com");
+        });
+
+        match config.get_param_any::<String>(&["any_missing_a", "any_missing_b"]) {
+            Err(ConfigError::NotFound(keys)) => assert_eq!(keys, "any_missing_a, any_missing_b"),
+            other => panic!("Expected NotFound, got {:?}", other),
+        }
+        assert!(matches!(
+            config.get_secret_any::<String>(&["any_missing_a", "any_missing_b"]),
+            Err(ConfigError::NotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_keys() -> Result<(), ConfigError> {
+        let config = Config::new_in_memory().with_defaults(HashMap::from([(
+            "list_default_theme".to_string(),
+            Value::String("dark".into()),
+        )]));
+        config.set_param("list_model", Value::String("gpt-4o".into()))?;
+        config.set_param("list_api_host", Value::String("localhost".into()))?;
+        config.set_secret("list_openai_key", Value::String("sk-1".into()))?;
+        config.rotate_secret(
+            "list_openai_key",
+            Value::String("sk-2".into()),
+            Duration::from_secs(60),
+        )?;
+
+        assert_eq!(config.list_params()?, vec!["list_api_host", "list_model"]);
+        assert_eq!(config.list_secret_keys()?, vec!["list_openai_key"]);
+
+        temp_env::with_vars(
+            [
+                ("LIST_MODEL", Some("o1")),
+                ("LIST_OPENAI_KEY", Some("sk-env")),
+            ],
+            || {
+                assert_eq!(
+                    config.list_params_with_sources().unwrap(),
+                    vec![
+                        ("list_api_host".to_string(), ValueSource::File),
+                        ("list_default_theme".to_string(), ValueSource::Default),
+                        ("list_model".to_string(), ValueSource::Env),
+                    ]
+                );
+                assert_eq!(
+                    config.list_secret_keys_with_sources().unwrap(),
+                    vec![("list_openai_key".to_string(), ValueSource::Env)]
+                );
+            },
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_len_and_is_empty() -> Result<(), ConfigError> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = Config::new(temp_dir.path().join("config.yaml"), TEST_KEYRING_SERVICE)?;
+        assert_eq!(config.len()?, 0);
+        assert!(config.is_empty()?);
+
+        config.set_param("len_provider", Value::String("openai".into()))?;
+        config.set_param("len_model", Value::String("gpt-4o".into()))?;
+        config.set_param("len_extensions", serde_json::json!({"developer": {}}))?;
+        assert_eq!(config.len()?, 3);
+        assert!(!config.is_empty()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_reader() -> Result<(), ConfigError> {
+        let sources = [
+            (
+                ConfigFormat::Yaml,
+                "provider: openai\nmax_turns: 5\nextensions:\n  developer:\n    enabled: true\n",
+            ),
+            (
+                ConfigFormat::Json,
+                r#"{"provider": "openai", "max_turns": 5, "extensions": {"developer": {"enabled": true}}}"#,
+            ),
+            (
+                ConfigFormat::Toml,
+                "provider = \"openai\"\nmax_turns = 5\n\n[extensions.developer]\nenabled = true\n",
+            ),
+        ];
+
+        for (format, content) in sources {
+            let config = Config::from_reader(content.as_bytes(), format)?;
+            assert_eq!(config.get_param::<String>("provider")?, "openai");
+            assert_eq!(config.get_param::<u32>("max_turns")?, 5);
+            assert_eq!(
+                config.get_param::<Value>("extensions")?,
+                serde_json::json!({"developer": {"enabled": true}})
+            );
+
+            // Writes stay in memory
+            config.set_param("model", Value::String("gpt-4o".into()))?;
+            assert_eq!(config.len()?, 4);
+            assert!(config.path_buf().is_none());
+        }
+
+        assert!(Config::from_reader("".as_bytes(), ConfigFormat::Json)?.is_empty()?);
+        assert!(matches!(
+            Config::from_reader("provider = ".as_bytes(), ConfigFormat::Toml),
+            Err(ConfigError::DeserializeError { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_all_replaces_everything() -> Result<(), ConfigError> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = Config::new_with_file_secrets(
+            temp_dir.path().join("config.yaml"),
+            temp_dir.path().join("secrets.yaml"),
+        )?;
+        config.set_param("provider", Value::String("openai".into()))?;
+        config.set_param("obsolete", Value::Bool(true))?;
+        config.set_secret("old_key", Value::String("old".into()))?;
+
+        config.set_all(HashMap::from([
+            ("provider".to_string(), Value::String("anthropic".into())),
+            ("model".to_string(), Value::String("claude".into())),
+        ]))?;
+        config.set_all_secrets(HashMap::from([(
+            "new_key".to_string(),
+            Value::String("new".into()),
+        )]))?;
+
+        assert_eq!(config.get_param::<String>("provider")?, "anthropic");
+        assert_eq!(config.get_param::<String>("model")?, "claude");
+        assert!(matches!(
+            config.get_param::<bool>("obsolete"),
+            Err(ConfigError::NotFound(_))
+        ));
+        assert_eq!(config.len()?, 2);
+
+        assert_eq!(config.get_secret::<String>("new_key")?, "new");
+        assert!(matches!(
+            config.get_secret::<String>("old_key"),
+            Err(ConfigError::NotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_in_then_save_once() -> Result<(), ConfigError> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = Config::new(temp_dir.path().join("config.yaml"), TEST_KEYRING_SERVICE)?;
+        config.set_param("provider", Value::String("openai".into()))?;
+
+        let mut values = config.load_values()?;
+        assert_eq!(config.get_from::<String>(&values, "provider")?, "openai");
+        Config::set_in(&mut values, "provider", Value::String("anthropic".into()));
+        Config::set_in(&mut values, "model", Value::String("claude".into()));
+        Config::set_in(&mut values, "max_turns", Value::from(10));
+        assert_eq!(config.get_from::<u32>(&values, "max_turns")?, 10);
+        assert!(matches!(
+            config.get_from::<String>(&values, "missing"),
+            Err(ConfigError::NotFound(_))
+        ));
+
+        // Nothing is persisted until the map is saved
+        assert_eq!(config.get_param::<String>("provider")?, "openai");
+        config.save_values(values)?;
+
+        assert_eq!(config.get_param::<String>("provider")?, "anthropic");
+        assert_eq!(config.get_param::<String>("model")?, "claude");
+        assert_eq!(config.get_param::<u32>("max_turns")?, 10);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_touch_creates_file_once() -> Result<(), ConfigError> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config_path = temp_dir.path().join("nested").join("config.yaml");
+        let config = Config::new(&config_path, TEST_KEYRING_SERVICE)?;
+
+        config.touch()?;
+        assert!(config_path.exists());
+        assert!(config.is_empty()?);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&config_path)?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        config.set_param("provider", Value::String("openai".into()))?;
+        config.touch()?;
+        assert_eq!(config.get_param::<String>("provider")?, "openai");
+
+        Config::new_in_memory().touch()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrations_upgrade_old_files() -> Result<(), ConfigError> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config_path = temp_dir.path().join("config.yaml");
+        std::fs::write(&config_path, "__config_version: 1\nGOOSE_MODEL: gpt-4o\n")?;
+
+        let config = Config::new(&config_path, TEST_KEYRING_SERVICE)?;
+        config.register_migration(1, 2, |values| {
+            if let Some(model) = values.remove("GOOSE_MODEL") {
+                values.insert("model".to_string(), model);
+            }
+        });
+
+        assert_eq!(config.get_param::<String>("model")?, "gpt-4o");
+        assert!(config.get_param::<String>("GOOSE_MODEL").is_err());
+        // The version key is never exposed as a value
+        assert!(config.get_param::<u32>("__config_version").is_err());
+        assert_eq!(config.len()?, 1);
+        assert_eq!(config.list_params()?, vec!["model"]);
 
-        config.set_param("key1", Value::String("value1".to_string()))?;
-        config.set_param("key2", Value::Number(42.into()))?;
+        // The upgrade was written back with the new version
+        let content = std::fs::read_to_string(&config_path)?;
+        let stored: HashMap<String, Value> = serde_yaml::from_str(&content)?;
+        assert_eq!(stored.get("__config_version"), Some(&Value::from(2)));
+        assert_eq!(stored.get("model"), Some(&Value::String("gpt-4o".into())));
+        assert!(!stored.contains_key("GOOSE_MODEL"));
 
-        // Read the file directly to check YAML formatting
-        let content = std::fs::read_to_string(temp_file.path())?;
-        assert!(content.contains("key1: value1"));
-        assert!(content.contains("key2: 42"));
+        // Already migrated files are left alone
+        config.set_param("GOOSE_MODEL", Value::String("unrelated".into()))?;
+        config.migrate()?;
+        assert_eq!(config.get_param::<String>("GOOSE_MODEL")?, "unrelated");
+
+        // A write to a file that still needs migrating migrates it under the
+        // same lock
+        let stale_path = temp_dir.path().join("stale.yaml");
+        std::fs::write(&stale_path, "__config_version: 1\nGOOSE_MODEL: claude\n")?;
+        let stale = Config::new(&stale_path, TEST_KEYRING_SERVICE)?;
+        stale.register_migration(1, 2, |values| {
+            if let Some(model) = values.remove("GOOSE_MODEL") {
+                values.insert("model".to_string(), model);
+            }
+        });
+        stale.set_param("theme", Value::String("dark".into()))?;
+        assert_eq!(stale.list_params()?, vec!["model", "theme"]);
 
         Ok(())
     }
 
     #[test]
-    fn test_value_management() -> Result<(), ConfigError> {
-        let temp_file = NamedTempFile::new().unwrap();
-        let config = Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?;
+    fn test_unversioned_config_is_upgraded() -> Result<(), ConfigError> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config_path = temp_dir.path().join("config.yaml");
+        std::fs::write(&config_path, "GOOSE_MODEL: gpt-4o\n")?;
 
-        config.set_param("key", Value::String("value".to_string()))?;
+        let rename_model = |values: &mut HashMap<String, Value>| {
+            if let Some(model) = values.remove("GOOSE_MODEL") {
+                values.insert("model".to_string(), model);
+            }
+        };
 
-        let value: String = config.get_param("key")?;
-        assert_eq!(value, "value");
+        // Migrations that change nothing leave the file untouched
+        let config = Config::new(&config_path, TEST_KEYRING_SERVICE)?;
+        config.register_migration(0, 1, |_| {});
+        config.migrate()?;
+        assert_eq!(
+            std::fs::read_to_string(&config_path)?,
+            "GOOSE_MODEL: gpt-4o\n"
+        );
 
-        config.delete("key")?;
+        // A file without a version key is at version 0, and is migrated through
+        // every step and persisted at the current version
+        let config = Config::new(&config_path, TEST_KEYRING_SERVICE)?;
+        config.register_migration(0, 1, rename_model);
+        config.register_migration(1, 3, |values| {
+            values.insert("theme".to_string(), Value::String("dark".into()));
+        });
+        config.migrate()?;
 
-        let result: Result<String, ConfigError> = config.get_param("key");
-        assert!(matches!(result, Err(ConfigError::NotFound(_))));
+        let content = std::fs::read_to_string(&config_path)?;
+        let stored: HashMap<String, Value> = serde_yaml::from_str(&content)?;
+        assert_eq!(stored.get("__config_version"), Some(&Value::from(3)));
+        assert_eq!(stored.get("model"), Some(&Value::String("gpt-4o".into())));
+        assert_eq!(stored.get("theme"), Some(&Value::String("dark".into())));
+        assert_eq!(config.list_params()?, vec!["model", "theme"]);
+
+        // Loading again is a no-op
+        config.migrate()?;
+        assert_eq!(std::fs::read_to_string(&config_path)?, content);
 
         Ok(())
     }
 
     #[test]
-    fn test_file_based_secrets_management() -> Result<(), ConfigError> {
-        let config_file = NamedTempFile::new().unwrap();
-        let secrets_file = NamedTempFile::new().unwrap();
-        let config = Config::new_with_file_secrets(config_file.path(), secrets_file.path())?;
+    fn test_get_param_source() -> Result<(), ConfigError> {
+        let config = Config::new_in_memory().with_defaults(HashMap::from([
+            ("source_model".to_string(), Value::String("gpt-4o".into())),
+            ("source_theme".to_string(), Value::String("dark".into())),
+        ]));
 
-        config.set_secret("key", Value::String("value".to_string()))?;
+        assert_eq!(config.get_param::<String>("source_model")?, "gpt-4o");
+        assert_eq!(
+            config.get_param_source("source_model")?,
+            ValueSource::Default
+        );
+        // Defaults aren't written to the store
+        assert!(config.is_empty()?);
 
-        let value: String = config.get_secret("key")?;
-        assert_eq!(value, "value");
+        config.set_param("source_model", Value::String("claude".into()))?;
+        assert_eq!(config.get_param::<String>("source_model")?, "claude");
+        assert_eq!(config.get_param_source("source_model")?, ValueSource::File);
 
-        config.delete_secret("key")?;
+        temp_env::with_var("SOURCE_THEME", Some("light"), || {
+            assert_eq!(config.get_param::<String>("source_theme").unwrap(), "light");
+            assert_eq!(
+                config.get_param_source("source_theme").unwrap(),
+                ValueSource::Env
+            );
+        });
 
-        let result: Result<String, ConfigError> = config.get_secret("key");
-        assert!(matches!(result, Err(ConfigError::NotFound(_))));
+        assert!(matches!(
+            config.get_param_source("source_missing"),
+            Err(ConfigError::NotFound(_))
+        ));
 
         Ok(())
     }
 
     #[test]
-    #[serial]
-    fn test_secret_management() -> Result<(), ConfigError> {
-        cleanup_keyring()?;
-        let temp_file = NamedTempFile::new().unwrap();
-        let config = Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?;
+    fn test_memory_secrets_are_zeroized() -> Result<(), ConfigError> {
+        let config = Config::new_in_memory();
+        let SecretStorage::Memory { values } = &config.secrets else {
+            panic!("expected in-memory secrets");
+        };
+        // Every value the store holds is in a buffer that zeroes itself on drop
+        let stored_plaintext = |secret: &str| {
+            let stored: &HashMap<String, Zeroizing<String>> = &values.lock().unwrap();
+            stored.values().any(|buffer| buffer.contains(secret))
+        };
 
-        // Test setting and getting a simple secret
-        config.set_secret("api_key", Value::String("secret123".to_string()))?;
-        let value: String = config.get_secret("api_key")?;
-        assert_eq!(value, "secret123");
+        config.set_secret("api_key", Value::String("hunter2".into()))?;
+        config.set_secret("other_key", Value::String("swordfish".into()))?;
+        assert!(stored_plaintext("hunter2"));
 
-        // Test environment variable override
-        std::env::set_var("API_KEY", "env_secret");
-        let value: String = config.get_secret("api_key")?;
-        assert_eq!(value, "env_secret");
-        std::env::remove_var("API_KEY");
+        // Replacing a value drops the buffer holding the old one
+        config.set_secret("api_key", Value::String("correct-horse".into()))?;
+        assert!(!stored_plaintext("hunter2"));
+        assert_eq!(config.get_secret::<String>("api_key")?, "correct-horse");
 
-        // Test deleting a secret
+        // So does deleting it
         config.delete_secret("api_key")?;
-        let result: Result<String, ConfigError> = config.get_secret("api_key");
-        assert!(matches!(result, Err(ConfigError::NotFound(_))));
+        assert!(!stored_plaintext("correct-horse"));
+        assert_eq!(values.lock().unwrap().len(), 1);
+
+        // Clearing the store drops every remaining buffer
+        config.set_all_secrets(HashMap::new())?;
+        assert!(values.lock().unwrap().is_empty());
 
-        cleanup_keyring()?;
         Ok(())
     }
 
     #[test]
-    #[serial]
-    fn test_multiple_secrets() -> Result<(), ConfigError> {
-        cleanup_keyring()?;
-        let temp_file = NamedTempFile::new().unwrap();
-        let config = Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?;
+    fn test_get_param_cached() -> Result<(), ConfigError> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config_path = temp_dir.path().join("config.yaml");
+        let config = Config::new(&config_path, TEST_KEYRING_SERVICE)?;
+        config.set_param("cached_model", Value::String("gpt-4o".into()))?;
+        let ttl = Duration::from_secs(60);
 
-        // Set multiple secrets
-        config.set_secret("key1", Value::String("secret1".to_string()))?;
-        config.set_secret("key2", Value::String("secret2".to_string()))?;
+        assert_eq!(
+            config.get_param_cached::<String>("cached_model", ttl)?,
+            "gpt-4o"
+        );
 
-        // Verify both exist
-        let value1: String = config.get_secret("key1")?;
-        let value2: String = config.get_secret("key2")?;
-        assert_eq!(value1, "secret1");
-        assert_eq!(value2, "secret2");
+        // Within the TTL the file isn't read again
+        std::fs::write(&config_path, "cached_model: edited\n")?;
+        assert_eq!(
+            config.get_param_cached::<String>("cached_model", ttl)?,
+            "gpt-4o"
+        );
+        assert_eq!(config.get_param::<String>("cached_model")?, "edited");
 
-        // Delete one secret
-        config.delete_secret("key1")?;
+        // An expired entry is read again
+        assert_eq!(
+            config.get_param_cached::<String>("cached_model", Duration::ZERO)?,
+            "edited"
+        );
 
-        // Verify key1 is gone but key2 remains
-        let result1: Result<String, ConfigError> = config.get_secret("key1");
-        let value2: String = config.get_secret("key2")?;
-        assert!(matches!(result1, Err(ConfigError::NotFound(_))));
-        assert_eq!(value2, "secret2");
+        // Setting the key invalidates the entry
+        config.set_param("cached_model", Value::String("claude".into()))?;
+        assert_eq!(
+            config.get_param_cached::<String>("cached_model", ttl)?,
+            "claude"
+        );
+
+        assert!(matches!(
+            config.get_param_cached::<String>("cached_missing", ttl),
+            Err(ConfigError::NotFound(_))
+        ));
 
-        cleanup_keyring()?;
         Ok(())
     }
 
     #[test]
-    fn test_concurrent_writes() -> Result<(), ConfigError> {
-        use std::sync::{Arc, Barrier, Mutex};
-        use std::thread;
+    fn test_with_app_strategy() {
+        let home = tempfile::tempdir().unwrap();
+        let home_path = home.path().to_string_lossy().to_string();
+        let config_home = home.path().join(".config").to_string_lossy().to_string();
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home_path.as_str())),
+                ("XDG_CONFIG_HOME", Some(config_home.as_str())),
+                ("GOOSE_DISABLE_KEYRING", Some("1")),
+            ],
+            || {
+                let config = Config::with_app_strategy(AppStrategyArgs {
+                    top_level_domain: "Example".to_string(),
+                    author: "Example".to_string(),
+                    app_name: "white-label".to_string(),
+                })
+                .unwrap();
+
+                let path = config.path_buf().unwrap();
+                assert!(path.starts_with(home.path()));
+                assert!(path.to_string_lossy().contains("white-label"));
+                assert_eq!(path.file_name().unwrap(), "config.yaml");
+                assert!(path.parent().unwrap().is_dir());
+            },
+        );
+    }
 
+    #[test]
+    fn test_env_only_secrets() -> Result<(), ConfigError> {
         let temp_file = NamedTempFile::new().unwrap();
-        let config = Arc::new(Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?);
-        let barrier = Arc::new(Barrier::new(3)); // For 3 concurrent threads
-        let values = Arc::new(Mutex::new(HashMap::new()));
-        let mut handles = vec![];
+        let config = Config::new_with_env_only_secrets(temp_file.path());
 
-        // Initialize with empty values
-        config.save_values(HashMap::new())?;
+        temp_env::with_var("ENV_ONLY_API_KEY", Some("from-env"), || {
+            let value: String = config.get_secret("env_only_api_key").unwrap();
+            assert_eq!(value, "from-env");
+        });
 
-        // Spawn 3 threads that will try to write simultaneously
-        for i in 0..3 {
-            let config = Arc::clone(&config);
-            let barrier = Arc::clone(&barrier);
-            let values = Arc::clone(&values);
-            let handle = thread::spawn(move || -> Result<(), ConfigError> {
-                // Wait for all threads to reach this point
-                barrier.wait();
+        assert!(matches!(
+            config.get_secret::<String>("env_only_api_key"),
+            Err(ConfigError::NotFound(_))
+        ));
+        assert!(matches!(
+            config.set_secret("env_only_api_key", Value::String("stored".into())),
+            Err(ConfigError::EnvOnlyReadOnly)
+        ));
+        assert!(matches!(
+            config.delete_secret("env_only_api_key"),
+            Err(ConfigError::EnvOnlyReadOnly)
+        ));
+        assert!(config.load_secrets()?.is_empty());
 
-                // Get the lock and update values
-                let mut values = values.lock().unwrap();
-                values.insert(format!("key{}", i), Value::String(format!("value{}", i)));
+        // Params are still persisted as usual
+        config.set_param("env_only_provider", Value::String("openai".into()))?;
+        assert_eq!(config.get_param::<String>("env_only_provider")?, "openai");
 
-                // Write all values
-                config.save_values(values.clone())?;
-                Ok(())
-            });
-            handles.push(handle);
-        }
+        Ok(())
+    }
 
-        // Wait for all threads to complete
-        for handle in handles {
-            handle.join().unwrap()?;
-        }
+    #[test]
+    fn test_rotate_secret_keeps_previous_for_grace_period() -> Result<(), ConfigError> {
+        let config = Config::new_in_memory();
+        let previous_keys = |config: &Config| -> Result<Vec<String>, ConfigError> {
+            let mut keys: Vec<String> = take_previous_secrets(&mut config.load_secret_store()?)
+                .into_keys()
+                .collect();
+            keys.sort();
+            Ok(keys)
+        };
 
-        // Verify all values were written correctly
-        let final_values = config.load_values()?;
+        // Nothing to keep when rotating a secret that was never set
+        config.rotate_secret("rotated_key", Value::String("first".into()), Duration::ZERO)?;
+        assert!(matches!(
+            config.get_previous_secret::<String>("rotated_key"),
+            Err(ConfigError::NotFound(_))
+        ));
 
-        // Print the final values for debugging
-        println!("Final values: {:?}", final_values);
+        config.rotate_secret(
+            "rotated_key",
+            Value::String("second".into()),
+            Duration::from_millis(300),
+        )?;
+        assert_eq!(config.get_secret::<String>("rotated_key")?, "second");
+        assert_eq!(
+            config.get_previous_secret::<String>("rotated_key")?,
+            "first"
+        );
 
+        // The previous value stays out of everything that lists secrets
+        assert_eq!(config.list_secret_keys()?, vec!["rotated_key"]);
+        assert!(!config.load_secrets()?.contains_key(PREVIOUS_SECRETS_KEY));
+        assert!(!config.contains_secret(PREVIOUS_SECRETS_KEY));
+        assert!(matches!(
+            config.get_secret::<Value>(PREVIOUS_SECRETS_KEY),
+            Err(ConfigError::NotFound(_))
+        ));
+        let export = config.export(ExportOptions {
+            include_secrets: true,
+        })?;
+        assert!(!export.contains(PREVIOUS_SECRETS_KEY) && !export.contains("first"));
+        assert!(!config
+            .effective_config()?
+            .to_string()
+            .contains(PREVIOUS_SECRETS_KEY));
+
+        // Secrets whose names look like previous values are ordinary secrets
+        config.set_secret("rotated_key__previous", Value::String("mine".into()))?;
+        assert!(config.contains_secret("rotated_key__previous"));
         assert_eq!(
-            final_values.len(),
-            3,
-            "Expected 3 values, got {}",
-            final_values.len()
+            config.get_previous_secret::<String>("rotated_key")?,
+            "first"
         );
+        config.delete_secret("rotated_key__previous")?;
 
-        for i in 0..3 {
-            let key = format!("key{}", i);
-            let value = format!("value{}", i);
-            assert!(
-                final_values.get(&key).is_some(),
-                "Missing key {} in final values",
-                key
-            );
-            assert_eq!(
-                final_values.get(&key).unwrap(),
-                &Value::String(value),
-                "Incorrect value for key {}",
-                key
-            );
-        }
+        std::thread::sleep(Duration::from_millis(400));
+        assert_eq!(config.get_secret::<String>("rotated_key")?, "second");
+        assert!(matches!(
+            config.get_previous_secret::<String>("rotated_key"),
+            Err(ConfigError::NotFound(_))
+        ));
+        // Reading an expired value removes it
+        assert!(previous_keys(&config)?.is_empty());
+
+        // Expired values of other secrets go on the next rotation
+        config.rotate_secret("other_key", Value::String("a".into()), Duration::ZERO)?;
+        config.rotate_secret("other_key", Value::String("b".into()), Duration::ZERO)?;
+        assert_eq!(previous_keys(&config)?, vec!["other_key"]);
+        config.rotate_secret(
+            "rotated_key",
+            Value::String("third".into()),
+            Duration::from_secs(60),
+        )?;
+        assert_eq!(previous_keys(&config)?, vec!["rotated_key"]);
+
+        // Deleting a secret deletes its previous value
+        config.delete_secret("rotated_key")?;
+        assert!(previous_keys(&config)?.is_empty());
 
         Ok(())
     }
 
     #[test]
-    fn test_concurrent_extension_writes() -> Result<(), ConfigError> {
-        use std::sync::{Arc, Barrier};
-        use std::thread;
-        use std::time::Duration;
+    fn test_path_buf() -> Result<(), ConfigError> {
+        assert_eq!(Config::new_in_memory().path_buf(), None);
 
         let temp_file = NamedTempFile::new().unwrap();
-        let config = Arc::new(Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?);
-        let barrier = Arc::new(Barrier::new(3)); // For 3 concurrent threads
-        let mut handles = vec![];
+        let config = Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?;
+        assert_eq!(config.path_buf(), Some(temp_file.path().to_path_buf()));
 
-        // Initialize with empty values
-        config.save_values(HashMap::new())?;
+        Ok(())
+    }
 
-        // Spawn 3 threads that will try to write extension configs simultaneously
-        for i in 0..3 {
-            let config = Arc::clone(&config);
-            let barrier = Arc::clone(&barrier);
-            let handle = thread::spawn(move || -> Result<(), ConfigError> {
-                // Wait for all threads to reach this point
-                barrier.wait();
+    #[test]
+    fn test_bom_and_crlf_files() -> Result<(), ConfigError> {
+        let config_file = NamedTempFile::new().unwrap();
+        let secrets_file = NamedTempFile::new().unwrap();
+        std::fs::write(
+            config_file.path(),
+            "\u{feff}provider: openai\r\nsettings:\r\n  port: 8080\r\n",
+        )?;
+        std::fs::write(secrets_file.path(), "\u{feff}api_key: sk-123\r\n")?;
+        let config = Config::new_with_file_secrets(config_file.path(), secrets_file.path())?;
 
-                // Add a small random delay to increase chance of concurrent access
-                thread::sleep(Duration::from_millis(i * 10));
+        assert_eq!(config.get_param::<String>("provider")?, "openai");
+        assert_eq!(
+            config.get_param::<Value>("settings")?,
+            serde_json::json!({"port": 8080})
+        );
+        assert_eq!(config.get_secret::<String>("api_key")?, "sk-123");
 
-                let extension_key = format!("extension_{}", i);
+        config.set_param("model", Value::String("gpt-4o".into()))?;
+        config.set_secret("other_key", Value::String("sk-456".into()))?;
 
-                // Use set_param which handles concurrent access properly
-                config.set_param(
-                    &extension_key,
-                    serde_json::json!({
-                        "name": format!("test_extension_{}", i),
-                        "version": format!("1.0.{}", i),
-                        "enabled": true,
-                        "settings": {
-                            "option1": format!("value{}", i),
-                            "option2": i
-                        }
-                    }),
-                )?;
-                Ok(())
-            });
-            handles.push(handle);
+        for path in [config_file.path(), secrets_file.path()] {
+            let content = std::fs::read_to_string(path)?;
+            assert!(!content.starts_with('\u{feff}'));
+            assert!(!content.contains('\r'));
         }
+        assert_eq!(config.get_param::<String>("provider")?, "openai");
+        assert_eq!(config.get_secret::<String>("api_key")?, "sk-123");
 
-        // Wait for all threads to complete
-        for handle in handles {
-            handle.join().unwrap()?;
-        }
+        Ok(())
+    }
 
-        // Verify all extension configs were written correctly
-        let final_values = config.load_values()?;
+    #[test]
+    fn test_transaction_commit_and_rollback() -> Result<(), ConfigError> {
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::new(temp_file.path(), TEST_KEYRING_SERVICE)?;
+        config.set_param("txn_provider", Value::String("openai".into()))?;
+        config.set_param("txn_obsolete", Value::Bool(true))?;
 
-        // Print the final values for debugging
-        println!("Final values: {:?}", final_values);
+        let mut transaction = config.begin_transaction();
+        transaction.set_param("txn_provider", Value::String("anthropic".into()));
+        transaction.set_param("txn_model", Value::String("claude".into()));
+        transaction.delete("txn_obsolete");
 
+        // Staged changes are visible in the transaction but not yet stored
         assert_eq!(
-            final_values.len(),
-            3,
-            "Expected 3 extension configs, got {}",
-            final_values.len()
+            transaction.get_param::<String>("txn_provider")?,
+            "anthropic"
         );
+        assert!(matches!(
+            transaction.get_param::<bool>("txn_obsolete"),
+            Err(ConfigError::NotFound(_))
+        ));
+        assert_eq!(config.get_param::<String>("txn_provider")?, "openai");
+        assert!(config.get_param::<bool>("txn_obsolete")?);
 
-        for i in 0..3 {
-            let extension_key = format!("extension_{}", i);
-
-            let config = final_values
-                .get(&extension_key)
-                .expect(&format!("Missing extension config for {}", extension_key));
+        transaction.commit()?;
+        assert_eq!(config.get_param::<String>("txn_provider")?, "anthropic");
+        assert_eq!(config.get_param::<String>("txn_model")?, "claude");
+        assert!(matches!(
+            config.get_param::<bool>("txn_obsolete"),
+            Err(ConfigError::NotFound(_))
+        ));
 
-            // Verify the structure matches what we wrote
-            let config_obj = config.as_object().unwrap();
-            assert_eq!(
-                config_obj.get("name").unwrap().as_str().unwrap(),
-                format!("test_extension_{}", i)
-            );
-            assert_eq!(
-                config_obj.get("version").unwrap().as_str().unwrap(),
-                format!("1.0.{}", i)
-            );
-            assert!(config_obj.get("enabled").unwrap().as_bool().unwrap());
+        let mut transaction = config.begin_transaction();
+        transaction.set_param("txn_provider", Value::String("ollama".into()));
+        transaction.rollback();
 
-            let settings = config_obj.get("settings").unwrap().as_object().unwrap();
-            assert_eq!(
-                settings.get("option1").unwrap().as_str().unwrap(),
-                format!("value{}", i)
-            );
-            assert_eq!(settings.get("option2").unwrap().as_i64().unwrap() as i32, i);
+        {
+            let mut transaction = config.begin_transaction();
+            transaction.delete("txn_model");
+            // Dropped without committing
         }
 
+        assert_eq!(config.get_param::<String>("txn_provider")?, "anthropic");
+        assert_eq!(config.get_param::<String>("txn_model")?, "claude");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_commit_is_all_or_nothing() -> Result<(), ConfigError> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        let config = Config::new_with_keyring_backend(
+            &config_path,
+            TEST_KEYRING_SERVICE,
+            Arc::new(MockKeyring::default()),
+        )?;
+        config.mark_secret("txn_api_key");
+        config.mark_secret("txn_old_key");
+        config.set_param("txn_provider", Value::String("openai".into()))?;
+        config.set_secret("txn_old_key", Value::String("sk-old".into()))?;
+
+        let mut transaction = config.begin_transaction();
+        transaction.set_param("txn_provider", Value::String("anthropic".into()));
+        transaction.set_param("txn_api_key", Value::String("sk-1".into()));
+        transaction.delete("txn_old_key");
+        transaction.commit()?;
+        assert_eq!(config.get_param::<String>("txn_provider")?, "anthropic");
+        assert_eq!(config.list_secret_keys()?, vec!["txn_api_key"]);
+
+        // When the secrets can't be written, none of the params are either
+        let read_only = Config::new_with_env_only_secrets(&config_path);
+        read_only.mark_secret("txn_api_key");
+        let mut transaction = read_only.begin_transaction();
+        transaction.set_param("txn_provider", Value::String("ollama".into()));
+        transaction.set_param("txn_api_key", Value::String("sk-2".into()));
+        assert!(matches!(
+            transaction.commit(),
+            Err(ConfigError::EnvOnlyReadOnly)
+        ));
+        assert_eq!(config.get_param::<String>("txn_provider")?, "anthropic");
+
+        // When the params can't be written, the secrets are rolled back
+        let blocker = dir.path().join("config.yaml.tmp");
+        std::fs::create_dir(&blocker)?;
+        let mut transaction = config.begin_transaction();
+        transaction.set_param("txn_provider", Value::String("ollama".into()));
+        transaction.delete("txn_api_key");
+        assert!(transaction.commit().is_err());
+        std::fs::remove_dir(&blocker)?;
+        assert_eq!(config.get_param::<String>("txn_provider")?, "anthropic");
+        assert_eq!(config.get_secret::<String>("txn_api_key")?, "sk-1");
+
         Ok(())
     }
 }
@@ -0,0 +1,75 @@
+use super::base::ConfigError;
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+// Marks a secrets file as encrypted rather than plaintext YAML
+const MAGIC: &[u8] = b"GOOSE-SECRETS-V1\n";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// Whether `contents` is in the encrypted secrets format
+pub(super) fn is_encrypted(contents: &[u8]) -> bool {
+    contents.starts_with(MAGIC)
+}
+
+/// Encrypt `plaintext` with a key derived from `passphrase`.
+///
+/// The output is the magic header, followed by a random argon2 salt, a random
+/// XChaCha20-Poly1305 nonce and the authenticated ciphertext.
+pub(super) fn encrypt(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>, ConfigError> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let key = derive_key(passphrase, &salt).map_err(ConfigError::SecretsEncryptionError)?;
+    let cipher = XChaCha20Poly1305::new(key.as_ref().into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|e| ConfigError::SecretsEncryptionError(e.to_string()))?;
+
+    let mut out = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + ciphertext.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+/// Decrypt contents produced by [`encrypt`].
+///
+/// A wrong passphrase and a tampered or truncated file are indistinguishable,
+/// and both are reported as `SecretsDecryptionError`.
+pub(super) fn decrypt(
+    contents: &[u8],
+    passphrase: &str,
+) -> Result<Zeroizing<Vec<u8>>, ConfigError> {
+    let body = contents
+        .strip_prefix(MAGIC)
+        .filter(|body| body.len() >= SALT_LEN + NONCE_LEN)
+        .ok_or_else(|| {
+            ConfigError::SecretsDecryptionError("secrets file is truncated".to_string())
+        })?;
+    let (salt, rest) = body.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let key = derive_key(passphrase, salt).map_err(ConfigError::SecretsDecryptionError)?;
+    let cipher = XChaCha20Poly1305::new(key.as_ref().into());
+    cipher
+        .decrypt(XNonce::from_slice(nonce), ciphertext)
+        .map(Zeroizing::new)
+        .map_err(|_| {
+            ConfigError::SecretsDecryptionError(
+                "wrong passphrase or corrupted secrets file".to_string(),
+            )
+        })
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>, String> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .map_err(|e| e.to_string())?;
+    Ok(key)
+}
@@ -1,13 +1,19 @@
 pub mod base;
+mod crypto;
 mod experiments;
 pub mod extensions;
 pub mod permission;
+pub mod watch;
 
 pub use crate::agents::ExtensionConfig;
-pub use base::{Config, ConfigError, APP_STRATEGY};
+pub use base::{
+    Config, ConfigError, ConfigFormat, DeserializeKind, ExportOptions, KeyringBackend, Migration,
+    ScopedConfig, SecretBackend, SystemKeyring, Transaction, ValueSource, APP_STRATEGY,
+};
 pub use experiments::ExperimentManager;
 pub use extensions::{ExtensionConfigManager, ExtensionEntry};
 pub use permission::PermissionManager;
+pub use watch::{ConfigChange, WatchHandle, DEFAULT_WATCH_DEBOUNCE};
 
 pub use extensions::DEFAULT_DISPLAY_NAME;
 pub use extensions::DEFAULT_EXTENSION;
@@ -0,0 +1,165 @@
+use super::base::ConfigError;
+use notify::{Event, RecursiveMode, Watcher};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How long the config file has to stay quiet before a change is delivered.
+pub const DEFAULT_WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+
+// How often the watcher thread checks whether it has been stopped
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A single key that changed in the config file.
+///
+/// `old` is `None` for keys that were added and `new` is `None` for keys
+/// that were removed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigChange {
+    pub key: String,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+// The per-key changes between two states of the config, ordered by key
+pub(super) fn diff_values(
+    old: &HashMap<String, Value>,
+    new: &HashMap<String, Value>,
+) -> Vec<ConfigChange> {
+    let keys: HashSet<&String> = old.keys().chain(new.keys()).collect();
+    let mut changes: Vec<ConfigChange> = keys
+        .into_iter()
+        .filter(|key| old.get(*key) != new.get(*key))
+        .map(|key| ConfigChange {
+            key: key.clone(),
+            old: old.get(key).cloned(),
+            new: new.get(key).cloned(),
+        })
+        .collect();
+    changes.sort_by(|a, b| a.key.cmp(&b.key));
+    changes
+}
+
+/// Keeps a config watcher running. Dropping the handle stops the watcher.
+pub struct WatchHandle {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WatchHandle {
+    // A handle for storage that never changes on disk, such as in-memory configs
+    pub(super) fn inactive() -> Self {
+        WatchHandle {
+            stop: Arc::new(AtomicBool::new(true)),
+            thread: None,
+        }
+    }
+
+    /// Stop watching and wait for the watcher thread to finish
+    pub fn stop(self) {
+        // Dropping does the work
+    }
+
+    // Let the watcher thread run on its own; it stops once its callback
+    // returns false
+    pub(super) fn detach(mut self) {
+        // Drop still runs, so leave it a flag the thread doesn't share
+        self.stop = Arc::new(AtomicBool::new(false));
+        self.thread.take();
+    }
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Watch a file and call `on_change` with its parsed contents once it settles.
+///
+/// The parent directory is watched rather than the file itself, so the watch
+/// survives writes that rename a temporary file over the target. Events are
+/// coalesced until no new ones arrive for `debounce`, which hides the partial
+/// states editors produce while saving (e.g. truncate then write). `on_change`
+/// only runs when the parsed contents differ from the last delivered state,
+/// starting from the contents at the time the watch began. Returning false
+/// from `on_change` stops the watcher.
+pub(super) fn spawn_watcher<L, F>(
+    path: PathBuf,
+    debounce: Duration,
+    load: L,
+    mut on_change: F,
+) -> Result<WatchHandle, ConfigError>
+where
+    L: Fn() -> Result<HashMap<String, Value>, ConfigError> + Send + 'static,
+    F: FnMut(&HashMap<String, Value>) -> bool + Send + 'static,
+{
+    let dir = path
+        .parent()
+        .map(|parent| parent.to_path_buf())
+        .unwrap_or_else(|| PathBuf::from("."));
+    std::fs::create_dir_all(&dir).map_err(|e| ConfigError::DirectoryError(e.to_string()))?;
+    let file_name = path.file_name().map(|name| name.to_os_string());
+
+    let (tx, rx) = mpsc::channel::<notify::Result<Event>>();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = tx.send(event);
+    })?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+
+    let touches_file = move |event: &Event| {
+        event
+            .paths
+            .iter()
+            .any(|changed| changed.file_name().map(|name| name.to_os_string()) == file_name)
+    };
+
+    let mut last = load().unwrap_or_default();
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
+    let thread = thread::spawn(move || {
+        // The watcher stops delivering events once dropped, so the thread owns it
+        let _watcher = watcher;
+
+        while !thread_stop.load(Ordering::SeqCst) {
+            match rx.recv_timeout(STOP_POLL_INTERVAL) {
+                Ok(Ok(event)) if touches_file(&event) => {}
+                Ok(_) | Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+
+            // Wait for the file to settle before reading it
+            loop {
+                match rx.recv_timeout(debounce) {
+                    Ok(_) => continue,
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+
+            match load() {
+                Ok(values) if values != last => {
+                    if !on_change(&values) {
+                        return;
+                    }
+                    last = values;
+                }
+                Ok(_) => {}
+                Err(e) => tracing::debug!("Skipping unreadable config change: {}", e),
+            }
+        }
+    });
+
+    Ok(WatchHandle {
+        stop,
+        thread: Some(thread),
+    })
+}
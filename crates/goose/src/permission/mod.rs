@@ -4,4 +4,8 @@ pub mod permission_store;
 
 pub use permission_confirmation::{Permission, PermissionConfirmation};
 pub use permission_judge::detect_read_only_tools;
-pub use permission_store::ToolPermissionStore;
+pub use permission_store::{
+    ApprovalStat, ArgumentPredicate, ArgumentRule, Clock, Decision, ExpireCallback,
+    PermissionDuration, PermissionPolicy, PermissionScope, PermissionStoreError,
+    PermissionStoreFormat, TimestampFormat, ToolPermissionStore,
+};
@@ -1,12 +1,44 @@
 use crate::message::ToolRequest;
-use anyhow::Result;
 use blake3::Hasher;
-use chrono::Utc;
-use etcetera::{choose_app_strategy, AppStrategy};
+use chrono::{DateTime, SecondsFormat, Utc};
+use etcetera::{choose_app_strategy, AppStrategy, AppStrategyArgs};
+use fs2::FileExt;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 use std::{fs::File, path::PathBuf};
+use thiserror::Error;
+
+const PERMISSIONS_FILE: &str = "tool_permissions.json";
+const PERMISSIONS_LOG_FILE: &str = "tool_permissions.jsonl";
+// Replaying a log with more operations than this on load triggers a compaction
+const LOG_COMPACTION_THRESHOLD: usize = 1000;
+const DEFAULT_MAX_TOTAL_RECORDS: usize = 10_000;
+const DEFAULT_MAX_RECORDS_PER_KEY: usize = 50;
+// Version 2 allows timestamps to be stored as RFC3339 strings
+const STORE_VERSION: u32 = 2;
+
+/// Errors returned by the tool permission store.
+///
+/// Like any error type this converts into `anyhow::Error` with `?`.
+#[derive(Error, Debug)]
+pub enum PermissionStoreError {
+    #[error("Failed to access tool permissions file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to serialize tool permissions: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("Failed to lock tool permissions file: {0}")]
+    Lock(String),
+    #[error("Invalid tool request: {0}")]
+    InvalidRequest(String),
+    #[error("Tool permissions need {size} bytes, more than the {max} byte limit")]
+    FileTooLarge { size: usize, max: usize },
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ToolPermissionRecord {
@@ -15,16 +47,425 @@ pub struct ToolPermissionRecord {
     context_hash: String, // Hash of the tool's arguments/context to differentiate similar calls
     #[serde(skip_serializing_if = "Option::is_none")] // Don't serialize if None
     readable_context: Option<String>, // Add this field
+    #[serde(deserialize_with = "deserialize_timestamp")]
     timestamp: i64,
+    #[serde(default, deserialize_with = "deserialize_optional_timestamp")]
     expiry: Option<i64>, // Optional expiry timestamp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    arguments: Option<Value>, // The tool arguments, used to detect drift from this context
+    #[serde(default = "default_approval_count")]
+    approval_count: u32, // How many times this decision was recorded in a row
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_optional_timestamp"
+    )]
+    last_approved: Option<i64>, // When the decision was last recorded, if more than once
+    #[serde(default)]
+    scope: PermissionScope, // Whether the decision covers one context or the whole tool
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    session_id: Option<String>, // Set when the decision only holds for the session that made it
+}
+
+fn in_session(record: &ToolPermissionRecord, session_id: &str) -> bool {
+    record.session_id.as_ref().is_none_or(|id| id == session_id)
+}
+
+// Stands in for the context hash of tool-wide records
+const TOOL_WIDE_CONTEXT: &str = "*";
+
+/// What a permission record applies to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PermissionScope {
+    /// Every call of the tool, whatever its arguments
+    ToolWide,
+    /// Only calls of the tool with the same arguments
+    #[default]
+    ContextSpecific,
+}
+
+/// How long a recorded decision holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionDuration {
+    /// Only for the current request; nothing is recorded
+    Once,
+    /// Until the store is loaded again by another session
+    Session,
+    /// For a fixed time from when it's recorded
+    For(Duration),
+    /// Until it's revoked
+    Forever,
+}
+
+impl PermissionDuration {
+    fn expiry_from(self, now: i64) -> Option<i64> {
+        match self {
+            PermissionDuration::For(duration) => Some(now + duration.as_secs() as i64),
+            _ => None,
+        }
+    }
+}
+
+// Keeps the `Option<Duration>` expiry that record_permission used to take working
+impl From<Option<Duration>> for PermissionDuration {
+    fn from(expiry: Option<Duration>) -> Self {
+        expiry.map_or(PermissionDuration::Forever, PermissionDuration::For)
+    }
+}
+
+impl From<Duration> for PermissionDuration {
+    fn from(duration: Duration) -> Self {
+        PermissionDuration::For(duration)
+    }
+}
+
+// The record fields holding timestamps
+const RECORD_TIMESTAMP_FIELDS: [&str; 3] = ["timestamp", "expiry", "last_approved"];
+
+/// How record timestamps are written to disk. Both forms are accepted on load.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampFormat {
+    /// Unix seconds, e.g. `1735689600`
+    #[default]
+    Unix,
+    /// RFC3339 strings, e.g. `2025-01-01T00:00:00Z`, which are easier to audit by hand
+    Rfc3339,
+}
+
+// A timestamp as found on disk: unix seconds or an RFC3339 string
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawTimestamp {
+    Unix(i64),
+    Rfc3339(String),
+}
+
+impl RawTimestamp {
+    fn into_unix<E: serde::de::Error>(self) -> Result<i64, E> {
+        match self {
+            RawTimestamp::Unix(seconds) => Ok(seconds),
+            RawTimestamp::Rfc3339(text) => DateTime::parse_from_rfc3339(&text)
+                .map(|time| time.timestamp())
+                .map_err(E::custom),
+        }
+    }
+}
+
+fn deserialize_timestamp<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<i64, D::Error> {
+    RawTimestamp::deserialize(deserializer)?.into_unix()
+}
+
+fn deserialize_optional_timestamp<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<i64>, D::Error> {
+    Option::<RawTimestamp>::deserialize(deserializer)?
+        .map(RawTimestamp::into_unix)
+        .transpose()
+}
+
+// Rewrite the timestamps of a serialized record in the given format
+fn format_record_timestamps(record: &mut Value, format: TimestampFormat) {
+    if format == TimestampFormat::Unix {
+        return;
+    }
+    for field in RECORD_TIMESTAMP_FIELDS {
+        if let Some(value) = record.get_mut(field) {
+            if let Some(time) = value
+                .as_i64()
+                .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+            {
+                *value = Value::String(time.to_rfc3339_opts(SecondsFormat::Secs, true));
+            }
+        }
+    }
+}
+
+fn default_approval_count() -> u32 {
+    1
+}
+
+impl ToolPermissionRecord {
+    pub fn tool_name(&self) -> &str {
+        &self.tool_name
+    }
+
+    pub fn allowed(&self) -> bool {
+        self.allowed
+    }
+
+    pub fn readable_context(&self) -> Option<&str> {
+        self.readable_context.as_deref()
+    }
+
+    /// When the decision was first recorded, in unix seconds
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    /// When the decision expires, in unix seconds
+    pub fn expiry(&self) -> Option<i64> {
+        self.expiry
+    }
+
+    pub fn scope(&self) -> PermissionScope {
+        self.scope
+    }
+
+    /// The session the decision is limited to, for [`PermissionDuration::Session`] records
+    pub fn session_id(&self) -> Option<&str> {
+        self.session_id.as_deref()
+    }
+
+    fn last_approved(&self) -> i64 {
+        self.last_approved.unwrap_or(self.timestamp)
+    }
+
+    // Fold a repeat of the same decision into this record, if it was still live
+    // and expires at the same time. A repeat with another expiry is kept as its
+    // own record so it can't shorten this one.
+    fn merge_repeat(&mut self, repeat: &ToolPermissionRecord) -> bool {
+        if self.allowed != repeat.allowed
+            || self.session_id != repeat.session_id
+            || self.expiry != repeat.expiry
+            || self.expiry.is_some_and(|exp| exp <= repeat.timestamp)
+        {
+            return false;
+        }
+
+        self.approval_count += repeat.approval_count;
+        self.last_approved = Some(repeat.last_approved());
+        self.readable_context = repeat.readable_context.clone();
+        self.arguments = repeat.arguments.clone();
+        true
+    }
+}
+
+// A portable allow decision, as written by export_allowlist
+#[derive(Debug, Serialize, Deserialize)]
+struct AllowlistEntry {
+    tool_name: String,
+    context_hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    readable_context: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    arguments: Option<Value>,
+}
+
+/// How often a tool call with a given context has been approved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApprovalStat {
+    pub tool_name: String,
+    pub readable_context: Option<String>,
+    pub approval_count: u32,
+    pub last_approved: i64,
+}
+
+/// The outcome of checking a tool request against the store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Allow,
+    Deny,
+    /// A similar but not identical call was allowed before, so the user should
+    /// confirm again now that the arguments changed
+    ReaskOnDrift,
+    Unknown,
+}
+
+/// How the permission store is persisted on disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PermissionStoreFormat {
+    /// A single JSON document that is rewritten on every save
+    #[default]
+    Json,
+    /// An append-only JSONL log of operations that is replayed on load, so
+    /// concurrent processes can append records without clobbering each other
+    Log,
+    /// Nothing is read from or written to disk, e.g. for tests or for
+    /// decisions that should only last as long as the process
+    Memory,
+}
+
+/// A single operation in the append-only permission log.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum PermissionLogOp {
+    Record {
+        key: String,
+        record: ToolPermissionRecord,
+    },
+    Revoke {
+        key: String,
+    },
+    Rule {
+        rule: ArgumentRule,
+    },
+    IgnoreArguments {
+        tool_name: String,
+        keys: Vec<String>,
+    },
+}
+
+/// Matches the tool argument at a dot-separated `path` (e.g. `command` or
+/// `options.mode`) against an exact value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArgumentPredicate {
+    pub path: String,
+    pub equals: Value,
+}
+
+impl ArgumentPredicate {
+    pub fn new(path: impl Into<String>, equals: Value) -> Self {
+        Self {
+            path: path.into(),
+            equals,
+        }
+    }
+
+    fn matches(&self, arguments: &Value) -> bool {
+        self.path
+            .split('.')
+            .try_fold(arguments, |value, segment| match value {
+                Value::Object(map) => map.get(segment),
+                Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+                _ => None,
+            })
+            .is_some_and(|value| value == &self.equals)
+    }
+}
+
+/// A decision for every call of a tool whose arguments satisfy all predicates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArgumentRule {
+    pub tool_name: String,
+    pub predicates: Vec<ArgumentPredicate>,
+    pub allowed: bool,
+}
+
+impl ArgumentRule {
+    fn matches(&self, tool_name: &str, arguments: &Value) -> bool {
+        self.tool_name == tool_name
+            && self
+                .predicates
+                .iter()
+                .all(|predicate| predicate.matches(arguments))
+    }
+}
+
+/// Standing allow/deny rules shipped by an organization, e.g. in
+/// `goose_permissions_policy.json`. The policy is read-only: it is consulted
+/// after the user's own decisions and never written back.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PermissionPolicy {
+    #[serde(default)]
+    pub allow: Vec<String>, // Tools that are allowed unless the user decided otherwise
+    #[serde(default)]
+    pub deny: Vec<String>, // Tools that are denied unless the user decided otherwise
+}
+
+impl PermissionPolicy {
+    /// Read a policy file, treating a missing file as an empty policy
+    pub fn load(path: &Path) -> Result<Self, PermissionStoreError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    // Deny rules win when a tool appears in both lists
+    fn decision(&self, tool_name: &str) -> Option<bool> {
+        if self.deny.iter().any(|tool| tool == tool_name) {
+            Some(false)
+        } else if self.allow.iter().any(|tool| tool == tool_name) {
+            Some(true)
+        } else {
+            None
+        }
+    }
+}
+
+/// Callback invoked with the tool name and readable context of a record
+/// that was removed because it expired.
+pub type ExpireCallback = Arc<dyn Fn(&str, Option<&str>) + Send + Sync>;
+
+// Wrapper so the store can keep deriving Debug
+#[derive(Clone)]
+struct OnExpire(ExpireCallback);
+
+impl std::fmt::Debug for OnExpire {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("OnExpire(..)")
+    }
+}
+
+/// Source of the current time in unix seconds, which tests can replace to
+/// move time forward without sleeping.
+pub type Clock = Arc<dyn Fn() -> i64 + Send + Sync>;
+
+// Wrapper so the store can keep deriving Debug, defaulting to the system clock
+#[derive(Clone)]
+struct StoreClock(Clock);
+
+impl Default for StoreClock {
+    fn default() -> Self {
+        StoreClock(Arc::new(|| Utc::now().timestamp()))
+    }
+}
+
+impl std::fmt::Debug for StoreClock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("StoreClock(..)")
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ToolPermissionStore {
     permissions: HashMap<String, Vec<ToolPermissionRecord>>,
     version: u32, // For future schema migrations
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    argument_rules: Vec<ArgumentRule>,
+    // Per tool, the top-level argument keys that don't affect the context hash
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    ignored_arguments: HashMap<String, Vec<String>>,
     #[serde(skip)] // Don't serialize this field
     permissions_dir: PathBuf,
+    #[serde(skip)]
+    format: PermissionStoreFormat,
+    #[serde(skip, default = "default_max_total_records")]
+    max_total_records: usize,
+    #[serde(skip, default = "default_max_records_per_key")]
+    max_records_per_key: usize,
+    #[serde(skip)]
+    max_file_bytes: Option<usize>,
+    #[serde(skip)]
+    policy: Option<PermissionPolicy>,
+    #[serde(skip)]
+    on_expire: Option<OnExpire>,
+    #[serde(skip)]
+    clock: StoreClock,
+    #[serde(skip)]
+    drift_threshold: Option<f64>,
+    #[serde(skip)]
+    timestamp_format: TimestampFormat,
+    #[serde(skip, default = "new_session_id")]
+    session_id: String,
+}
+
+fn default_max_total_records() -> usize {
+    DEFAULT_MAX_TOTAL_RECORDS
+}
+
+fn default_max_records_per_key() -> usize {
+    DEFAULT_MAX_RECORDS_PER_KEY
+}
+
+// Each store instance is its own session for PermissionDuration::Session
+fn new_session_id() -> String {
+    uuid::Uuid::new_v4().to_string()
 }
 
 impl Default for ToolPermissionStore {
@@ -35,43 +476,209 @@ impl Default for ToolPermissionStore {
 
 impl ToolPermissionStore {
     pub fn new() -> Self {
-        let permissions_dir = choose_app_strategy(crate::config::APP_STRATEGY.clone())
-            .map(|strategy| strategy.config_dir())
-            .unwrap_or_else(|_| PathBuf::from(".config/goose"));
+        Self::with_dir(app_config_dir(crate::config::APP_STRATEGY.clone()))
+    }
 
+    /// Create an empty store that is saved under `permissions_dir` instead of
+    /// the goose config directory, e.g. for tests or per-profile permissions.
+    ///
+    /// Nothing is read from the directory; use [`Self::load_from`] for that.
+    pub fn with_dir(permissions_dir: impl Into<PathBuf>) -> Self {
         Self {
             permissions: HashMap::new(),
-            version: 1,
-            permissions_dir,
+            version: STORE_VERSION,
+            argument_rules: Vec::new(),
+            ignored_arguments: HashMap::new(),
+            permissions_dir: permissions_dir.into(),
+            format: PermissionStoreFormat::default(),
+            max_total_records: DEFAULT_MAX_TOTAL_RECORDS,
+            max_records_per_key: DEFAULT_MAX_RECORDS_PER_KEY,
+            max_file_bytes: None,
+            policy: None,
+            on_expire: None,
+            clock: StoreClock::default(),
+            drift_threshold: None,
+            timestamp_format: TimestampFormat::default(),
+            session_id: new_session_id(),
         }
     }
 
-    pub fn load() -> Result<Self> {
+    /// Limit the total number of records kept across all tools.
+    ///
+    /// When a save would exceed the limit, expired records are dropped first,
+    /// then the least recently recorded decisions until the store fits.
+    pub fn with_max_total_records(mut self, max_total_records: usize) -> Self {
+        self.max_total_records = max_total_records;
+        self
+    }
+
+    /// Limit how many records are kept for a single tool context.
+    ///
+    /// Only the latest record decides a request, so when recording a decision
+    /// exceeds the limit the oldest records of that context are dropped.
+    pub fn with_max_records_per_key(mut self, max_records_per_key: usize) -> Self {
+        self.max_records_per_key = max_records_per_key;
+        self
+    }
+
+    /// Cap the size of the JSON permissions file.
+    ///
+    /// When a save would exceed the cap, expired and then the least recently
+    /// recorded decisions are dropped until the file fits. Saving only fails
+    /// if the file is still too large with a single record left.
+    pub fn with_max_file_bytes(mut self, max_file_bytes: usize) -> Self {
+        self.max_file_bytes = Some(max_file_bytes);
+        self
+    }
+
+    /// Set a callback to be notified when [`Self::cleanup_expired`] removes a
+    /// record because it expired, e.g. to tell the user an approval lapsed.
+    pub fn with_on_expire<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&str, Option<&str>) + Send + Sync + 'static,
+    {
+        self.on_expire = Some(OnExpire(Arc::new(callback)));
+        self
+    }
+
+    /// Detect argument drift in [`Self::decide`].
+    ///
+    /// When no decision matches a request exactly but a previous allow for the
+    /// same tool has arguments at least `threshold` similar (0.0 to 1.0, by the
+    /// overlap of top-level argument keys and values), the request is reported
+    /// as [`Decision::ReaskOnDrift`] instead of [`Decision::Unknown`].
+    pub fn with_drift_threshold(mut self, threshold: f64) -> Self {
+        self.drift_threshold = Some(threshold);
+        self
+    }
+
+    /// Write record timestamps in the given format when saving.
+    pub fn with_timestamp_format(mut self, timestamp_format: TimestampFormat) -> Self {
+        self.timestamp_format = timestamp_format;
+        self
+    }
+
+    /// Use `clock` instead of the system time for recording, checking and
+    /// expiring decisions.
+    pub fn with_clock<F>(mut self, clock: F) -> Self
+    where
+        F: Fn() -> i64 + Send + Sync + 'static,
+    {
+        self.clock = StoreClock(Arc::new(clock));
+        self
+    }
+
+    // The current time in unix seconds
+    fn now(&self) -> i64 {
+        (self.clock.0)()
+    }
+
+    pub fn load() -> Result<Self, PermissionStoreError> {
+        Self::load_with_format(PermissionStoreFormat::default())
+    }
+
+    /// Load the store along with a read-only organization policy.
+    ///
+    /// The policy is consulted by [`ToolPermissionStore::check_permission`]
+    /// after the user's recorded decisions, so the org baseline applies to tools
+    /// the user hasn't decided on. Recording and saving never modify the policy.
+    pub fn load_with_policy(policy_path: PathBuf) -> Result<Self, PermissionStoreError> {
+        let mut store = Self::load()?;
+        store.policy = Some(PermissionPolicy::load(&policy_path)?);
+        Ok(store)
+    }
+
+    /// Load the store saved under `permissions_dir`, as created with
+    /// [`Self::with_dir`]. A directory that doesn't exist yet gives an empty
+    /// store.
+    pub fn load_from(permissions_dir: impl Into<PathBuf>) -> Result<Self, PermissionStoreError> {
+        Self::load_from_dir(permissions_dir.into(), PermissionStoreFormat::default())
+    }
+
+    /// Load the store from the config directory of the given app, matching
+    /// [`Config::with_app_strategy`](crate::config::Config::with_app_strategy).
+    pub fn load_with_app_strategy(
+        args: AppStrategyArgs,
+        format: PermissionStoreFormat,
+    ) -> Result<Self, PermissionStoreError> {
+        Self::load_from_dir(app_config_dir(args), format)
+    }
+
+    /// Load the store using the given on-disk format.
+    ///
+    /// The JSON format and the log format use separate files, so switching
+    /// formats starts from an empty store.
+    pub fn load_with_format(format: PermissionStoreFormat) -> Result<Self, PermissionStoreError> {
         let store = Self::new();
-        let file_path = store.permissions_dir.join("tool_permissions.json");
+        Self::load_from_dir(store.permissions_dir, format)
+    }
 
-        if !file_path.exists() {
-            return Ok(store);
-        }
+    fn load_from_dir(
+        permissions_dir: PathBuf,
+        format: PermissionStoreFormat,
+    ) -> Result<Self, PermissionStoreError> {
+        let mut store = Self {
+            format,
+            ..Self::with_dir(permissions_dir)
+        };
+
+        match format {
+            PermissionStoreFormat::Json => {
+                let file_path = store.permissions_dir.join(PERMISSIONS_FILE);
 
-        let file = File::open(file_path)?;
-        let mut permissions: ToolPermissionStore = serde_json::from_reader(file)?;
-        permissions.permissions_dir = store.permissions_dir;
+                if !file_path.exists() {
+                    return Ok(store);
+                }
+
+                let file = File::open(file_path)?;
+                let persisted: ToolPermissionStore = serde_json::from_reader(file)?;
+                store.permissions = persisted.permissions;
+                store.argument_rules = persisted.argument_rules;
+                store.ignored_arguments = persisted.ignored_arguments;
+                // Older versions are upgraded when the store is next saved
+            }
+            PermissionStoreFormat::Log => {
+                let op_count = store.replay_log()?;
+                if op_count > LOG_COMPACTION_THRESHOLD {
+                    store.compact_log()?;
+                }
+            }
+            PermissionStoreFormat::Memory => {}
+        }
 
         // Clean up expired entries on load
-        permissions.cleanup_expired()?;
+        store.cleanup_expired()?;
 
-        Ok(permissions)
+        Ok(store)
     }
 
-    pub fn save(&self) -> anyhow::Result<()> {
+    /// Write the store to disk.
+    ///
+    /// The log format appended every change as it was made, so this doesn't
+    /// write anything for it; rewriting the log would give up its crash
+    /// safety. The log is only rewritten by [`Self::compact_log`], which also
+    /// runs on load once the log grows past a threshold.
+    pub fn save(&mut self) -> Result<(), PermissionStoreError> {
+        let now = self.now();
+        let evicted = evict_oldest_records(&mut self.permissions, self.max_total_records, now);
+        if evicted > 0 {
+            tracing::debug!("Evicted {} tool permission records over the limit", evicted);
+        }
+        if matches!(
+            self.format,
+            PermissionStoreFormat::Log | PermissionStoreFormat::Memory
+        ) {
+            return Ok(());
+        }
+
+        let content = self.serialize_within_file_limit(now)?;
+
         std::fs::create_dir_all(&self.permissions_dir)?;
 
-        let path = self.permissions_dir.join("tool_permissions.json");
+        let path = self.permissions_dir.join(PERMISSIONS_FILE);
         let temp_path = path.with_extension("tmp");
 
         // Write complete content to temporary file
-        let content = serde_json::to_string_pretty(self)?;
         std::fs::write(&temp_path, &content)?;
 
         // Atomically rename temp file to target file
@@ -80,70 +687,2101 @@ impl ToolPermissionStore {
         Ok(())
     }
 
+    fn to_json(&self) -> Result<String, PermissionStoreError> {
+        let mut value = serde_json::to_value(self)?;
+        if let Some(permissions) = value.get_mut("permissions").and_then(Value::as_object_mut) {
+            for record in permissions
+                .values_mut()
+                .filter_map(Value::as_array_mut)
+                .flatten()
+            {
+                format_record_timestamps(record, self.timestamp_format);
+            }
+        }
+        Ok(serde_json::to_string_pretty(&value)?)
+    }
+
+    // Serialize the store, evicting the oldest records until it fits in
+    // max_file_bytes. If even a single record doesn't fit, nothing is evicted.
+    fn serialize_within_file_limit(&mut self, now: i64) -> Result<String, PermissionStoreError> {
+        let mut content = self.to_json()?;
+        let Some(max) = self.max_file_bytes else {
+            return Ok(content);
+        };
+
+        let original = (content.len() > max).then(|| self.permissions.clone());
+        let mut evicted = 0;
+        while content.len() > max {
+            let total: usize = self.permissions.values().map(Vec::len).sum();
+            if total <= 1 {
+                if let Some(original) = original {
+                    self.permissions = original;
+                }
+                return Err(PermissionStoreError::FileTooLarge {
+                    size: content.len(),
+                    max,
+                });
+            }
+
+            // Shrink roughly in proportion to the excess, keeping at least one
+            // record and dropping at least one
+            let keep = (total * max / content.len()).clamp(1, total - 1);
+            evicted += evict_oldest_records(&mut self.permissions, keep, now);
+            content = self.to_json()?;
+        }
+
+        if evicted > 0 {
+            tracing::info!(
+                "Evicted {} tool permission records to stay within {} bytes",
+                evicted,
+                max
+            );
+        }
+        Ok(content)
+    }
+
     pub fn check_permission(&self, tool_request: &ToolRequest) -> Option<bool> {
-        let context_hash = self.hash_tool_context(tool_request);
-        let tool_call = tool_request.tool_call.as_ref().unwrap();
-        let key = format!("{}:{}", tool_call.name, context_hash);
+        self.active_record(tool_request)
+            .map(|record| record.allowed)
+            .or_else(|| self.argument_rule_decision(tool_request))
+            .or_else(|| {
+                let tool_call = tool_request.tool_call.as_ref().ok()?;
+                self.policy.as_ref()?.decision(&tool_call.name)
+            })
+    }
 
-        self.permissions.get(&key).and_then(|records| {
-            records
-                .iter()
-                .filter(|record| record.expiry.is_none_or(|exp| exp > Utc::now().timestamp()))
-                .next_back()
-                .map(|record| record.allowed)
+    /// Decide a tool request, distinguishing a drifted previous allow from a
+    /// request that was never seen.
+    ///
+    /// Exact matches, argument rules and the policy are consulted as in
+    /// [`Self::check_permission`]. Drift is only reported when a threshold was
+    /// set with [`Self::with_drift_threshold`].
+    pub fn decide(&self, tool_request: &ToolRequest) -> Decision {
+        match self.check_permission(tool_request) {
+            Some(true) => Decision::Allow,
+            Some(false) => Decision::Deny,
+            None if self.has_similar_allow(tool_request) => Decision::ReaskOnDrift,
+            None => Decision::Unknown,
+        }
+    }
+
+    fn has_similar_allow(&self, tool_request: &ToolRequest) -> bool {
+        let (Some(threshold), Ok(tool_call)) = (self.drift_threshold, &tool_request.tool_call)
+        else {
+            return false;
+        };
+        let now = self.now();
+
+        self.permissions
+            .values()
+            .flatten()
+            .filter(|record| {
+                record.allowed && record.tool_name == tool_call.name && self.is_live(record, now)
+            })
+            .filter_map(|record| record.arguments.as_ref())
+            .any(|arguments| argument_similarity(arguments, &tool_call.arguments) >= threshold)
+    }
+
+    /// Every stored record with its key, including expired ones that haven't
+    /// been cleaned up yet. Intended for admin and debugging views; it never
+    /// modifies the store.
+    pub fn all_records_including_expired(
+        &self,
+    ) -> impl Iterator<Item = (&str, &ToolPermissionRecord)> {
+        self.permissions
+            .iter()
+            .flat_map(|(key, records)| records.iter().map(move |record| (key.as_str(), record)))
+    }
+
+    /// The latest recorded decision for this exact tool request, even if it
+    /// has expired.
+    ///
+    /// Unlike [`Self::check_permission`] this ignores argument rules and the
+    /// policy, and it never modifies the store. Intended for debugging stale
+    /// decisions.
+    pub fn check_permission_ignoring_expiry(&self, tool_request: &ToolRequest) -> Option<bool> {
+        self.find_record(tool_request, |record| self.in_session(record))
+            .map(|record| record.allowed)
+    }
+
+    /// Check whether the decision for this tool request is about to lapse.
+    ///
+    /// Returns true when a non-expired record matches and its expiry falls
+    /// within `threshold` from now (a record expiring exactly at the threshold
+    /// counts). Records without an expiry, missing records and already expired
+    /// records all return false.
+    pub fn expiring_within(&self, tool_request: &ToolRequest, threshold: Duration) -> bool {
+        let now = self.now();
+        self.active_record(tool_request)
+            .and_then(|record| record.expiry)
+            .is_some_and(|exp| exp - now <= threshold.as_secs() as i64)
+    }
+
+    /// Add a rule deciding every call of `tool_name` whose arguments satisfy
+    /// all `predicates`, such as allowing `text_editor` when `command` is `view`.
+    ///
+    /// Rules are consulted by [`ToolPermissionStore::check_permission`] when no
+    /// recorded decision matches the exact arguments. When several rules match,
+    /// the most recently added one wins.
+    pub fn add_argument_rule(
+        &mut self,
+        tool_name: &str,
+        predicates: Vec<ArgumentPredicate>,
+        allowed: bool,
+    ) -> Result<(), PermissionStoreError> {
+        let rule = ArgumentRule {
+            tool_name: tool_name.to_string(),
+            predicates,
+            allowed,
+        };
+        self.apply_and_persist(PermissionLogOp::Rule { rule })
+    }
+
+    /// Ignore the given top-level argument keys of `tool_name` when matching
+    /// recorded decisions, so calls that differ only in those arguments (such
+    /// as an optional `limit`) share an approval.
+    ///
+    /// This replaces any keys previously ignored for the tool. Decisions
+    /// recorded before the change were matched on the old keys and may no
+    /// longer apply.
+    pub fn set_ignored_arguments(
+        &mut self,
+        tool_name: &str,
+        keys: Vec<String>,
+    ) -> Result<(), PermissionStoreError> {
+        self.apply_and_persist(PermissionLogOp::IgnoreArguments {
+            tool_name: tool_name.to_string(),
+            keys,
         })
     }
 
+    fn argument_rule_decision(&self, tool_request: &ToolRequest) -> Option<bool> {
+        let tool_call = tool_request.tool_call.as_ref().ok()?;
+        self.argument_rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(&tool_call.name, &tool_call.arguments))
+            .map(|rule| rule.allowed)
+    }
+
+    // The most recent non-expired record matching this tool request, falling
+    // back to a tool-wide record
+    fn active_record(&self, tool_request: &ToolRequest) -> Option<&ToolPermissionRecord> {
+        let now = self.now();
+        self.find_record(tool_request, |record| self.is_live(record, now))
+    }
+
+    // The most recent record for this tool request accepted by `accept`,
+    // falling back to a tool-wide record when no context-specific one is. A
+    // context-specific decision always wins, so a deny for one call overrides
+    // a tool-wide allow.
+    fn find_record(
+        &self,
+        tool_request: &ToolRequest,
+        accept: impl Fn(&ToolPermissionRecord) -> bool,
+    ) -> Option<&ToolPermissionRecord> {
+        let context_hash = self.hash_tool_context(tool_request).ok()?;
+        let tool_call = tool_request.tool_call.as_ref().ok()?;
+        let key = format!("{}:{}", tool_call.name, context_hash);
+
+        let latest = |key: &str| {
+            self.permissions
+                .get(key)
+                .and_then(|records| records.iter().rfind(|record| accept(record)))
+        };
+        latest(&key).or_else(|| latest(&tool_wide_key(&tool_call.name)))
+    }
+
+    // Session records only count in the session that recorded them
+    fn in_session(&self, record: &ToolPermissionRecord) -> bool {
+        in_session(record, &self.session_id)
+    }
+
+    // Whether a record is still in effect: not expired, and not a session
+    // grant from another session
+    fn is_live(&self, record: &ToolPermissionRecord, now: i64) -> bool {
+        record.expiry.is_none_or(|exp| exp > now) && self.in_session(record)
+    }
+
+    /// The id [`PermissionDuration::Session`] records made by this store are
+    /// tagged with. A fresh id is generated for every store instance.
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Record a decision for this exact tool request.
+    ///
+    /// `duration` also accepts an `Option<Duration>` expiry, where `None`
+    /// means [`PermissionDuration::Forever`]. [`PermissionDuration::Once`]
+    /// records nothing.
     pub fn record_permission(
         &mut self,
         tool_request: &ToolRequest,
         allowed: bool,
-        expiry_duration: Option<Duration>,
-    ) -> anyhow::Result<()> {
-        let context_hash = self.hash_tool_context(tool_request);
-        let tool_call = tool_request.tool_call.as_ref().unwrap();
+        duration: impl Into<PermissionDuration>,
+    ) -> Result<(), PermissionStoreError> {
+        let tool_call = tool_request
+            .tool_call
+            .as_ref()
+            .map_err(|e| PermissionStoreError::InvalidRequest(e.to_string()))?;
+        let duration = duration.into();
+        if duration == PermissionDuration::Once {
+            return Ok(());
+        }
+        let context_hash = self.hash_tool_context(tool_request)?;
         let key = format!("{}:{}", tool_call.name, context_hash);
+        let now = self.now();
 
         let record = ToolPermissionRecord {
             tool_name: tool_call.name.clone(),
             allowed,
             context_hash,
             readable_context: Some(tool_request.to_readable_string()),
-            timestamp: Utc::now().timestamp(),
-            expiry: expiry_duration.map(|d| Utc::now().timestamp() + d.as_secs() as i64),
+            timestamp: now,
+            expiry: duration.expiry_from(now),
+            arguments: Some(tool_call.arguments.clone()),
+            approval_count: 1,
+            last_approved: None,
+            scope: PermissionScope::ContextSpecific,
+            session_id: self.session_tag(duration),
         };
 
-        self.permissions.entry(key).or_default().push(record);
+        self.apply_and_persist(PermissionLogOp::Record { key, record })
+    }
 
-        self.save()?;
-        Ok(())
+    /// Record a decision for every call of `tool_name`, whatever its arguments.
+    ///
+    /// [`Self::check_permission`] uses this when no context-specific decision
+    /// matches, so a specific call can still be denied while the tool as a
+    /// whole is allowed.
+    pub fn record_tool_wide_permission(
+        &mut self,
+        tool_name: &str,
+        allowed: bool,
+        duration: impl Into<PermissionDuration>,
+    ) -> Result<(), PermissionStoreError> {
+        let duration = duration.into();
+        if duration == PermissionDuration::Once {
+            return Ok(());
+        }
+        let now = self.now();
+        let record = ToolPermissionRecord {
+            tool_name: tool_name.to_string(),
+            allowed,
+            context_hash: TOOL_WIDE_CONTEXT.to_string(),
+            readable_context: None,
+            timestamp: now,
+            expiry: duration.expiry_from(now),
+            arguments: None,
+            approval_count: 1,
+            last_approved: None,
+            scope: PermissionScope::ToolWide,
+            session_id: self.session_tag(duration),
+        };
+
+        self.apply_and_persist(PermissionLogOp::Record {
+            key: tool_wide_key(tool_name),
+            record,
+        })
+    }
+
+    fn session_tag(&self, duration: PermissionDuration) -> Option<String> {
+        (duration == PermissionDuration::Session).then(|| self.session_id.clone())
+    }
+
+    // Apply an operation in memory and persist it in the store's format: the
+    // JSON format rewrites the whole file, the log format appends the operation
+    fn apply_and_persist(&mut self, op: PermissionLogOp) -> Result<(), PermissionStoreError> {
+        self.apply_and_persist_all(vec![op])
+    }
+
+    // Like apply_and_persist for several operations, rewriting the JSON file
+    // only once after all of them are applied
+    fn apply_and_persist_all(
+        &mut self,
+        ops: Vec<PermissionLogOp>,
+    ) -> Result<(), PermissionStoreError> {
+        if ops.is_empty() {
+            return Ok(());
+        }
+
+        for op in ops {
+            if self.format == PermissionStoreFormat::Log {
+                self.append_log_op(&op)?;
+            }
+            self.apply_log_op(op);
+        }
+
+        match self.format {
+            PermissionStoreFormat::Json => self.save(),
+            PermissionStoreFormat::Log | PermissionStoreFormat::Memory => Ok(()),
+        }
+    }
+
+    /// Remove every recorded decision for `tool_name`, including tool-wide
+    /// ones. Returns how many contexts were revoked.
+    pub fn revoke(&mut self, tool_name: &str) -> Result<usize, PermissionStoreError> {
+        let ops: Vec<PermissionLogOp> = self
+            .permissions
+            .iter()
+            .filter(|(_, records)| records.iter().any(|record| record.tool_name == tool_name))
+            .map(|(key, _)| PermissionLogOp::Revoke { key: key.clone() })
+            .collect();
+        let count = ops.len();
+
+        self.apply_and_persist_all(ops)?;
+        Ok(count)
+    }
+
+    /// Remove the decisions recorded for exactly this tool request, leaving
+    /// other contexts and tool-wide decisions in place. Returns whether
+    /// anything was revoked.
+    pub fn revoke_context(
+        &mut self,
+        tool_request: &ToolRequest,
+    ) -> Result<bool, PermissionStoreError> {
+        let tool_call = tool_request
+            .tool_call
+            .as_ref()
+            .map_err(|e| PermissionStoreError::InvalidRequest(e.to_string()))?;
+        let key = format!(
+            "{}:{}",
+            tool_call.name,
+            self.hash_tool_context(tool_request)?
+        );
+        if !self.permissions.contains_key(&key) {
+            return Ok(false);
+        }
+
+        self.apply_and_persist(PermissionLogOp::Revoke { key })?;
+        Ok(true)
+    }
+
+    /// The decision currently in effect for each recorded context, sorted by
+    /// tool name and then by when it was recorded.
+    ///
+    /// Expired records and session grants from other sessions are left out
+    /// even while they're still stored.
+    pub fn list_permissions(&self) -> Vec<&ToolPermissionRecord> {
+        let now = self.now();
+        let mut records: Vec<&ToolPermissionRecord> = self
+            .permissions
+            .values()
+            .filter_map(|records| records.iter().rfind(|record| self.is_live(record, now)))
+            .collect();
+        records.sort_by(|a, b| (&a.tool_name, a.timestamp).cmp(&(&b.tool_name, b.timestamp)));
+        records
     }
 
-    fn hash_tool_context(&self, tool_request: &ToolRequest) -> String {
+    fn apply_log_op(&mut self, op: PermissionLogOp) {
+        match op {
+            PermissionLogOp::Record { key, record } => {
+                // Repeating the latest decision bumps its count instead of
+                // appending an identical record
+                let records = self.permissions.entry(key).or_default();
+                if !records
+                    .last_mut()
+                    .is_some_and(|latest| latest.merge_repeat(&record))
+                {
+                    records.push(record);
+                    prune_oldest_in_key(records, self.max_records_per_key);
+                }
+            }
+            PermissionLogOp::Revoke { key } => {
+                self.permissions.remove(&key);
+            }
+            PermissionLogOp::Rule { rule } => self.argument_rules.push(rule),
+            PermissionLogOp::IgnoreArguments { tool_name, keys } => {
+                if keys.is_empty() {
+                    self.ignored_arguments.remove(&tool_name);
+                } else {
+                    self.ignored_arguments.insert(tool_name, keys);
+                }
+            }
+        }
+    }
+
+    fn hash_tool_context(
+        &self,
+        tool_request: &ToolRequest,
+    ) -> Result<String, PermissionStoreError> {
         // Create a hash of the tool's arguments to differentiate similar calls
         // This helps identify when the same tool is being used in a different context
+        let tool_call = tool_request
+            .tool_call
+            .as_ref()
+            .map_err(|e| PermissionStoreError::InvalidRequest(e.to_string()))?;
+        let mut arguments = tool_call.arguments.clone();
+        if let (Some(ignored), Value::Object(map)) =
+            (self.ignored_arguments.get(&tool_call.name), &mut arguments)
+        {
+            map.retain(|key, _| !ignored.contains(key));
+        }
+
         let mut hasher = Hasher::new();
         hasher.update(
-            serde_json::to_string(&tool_request.tool_call.as_ref().unwrap().arguments)
+            serde_json::to_string(&arguments)
                 .unwrap_or_default()
                 .as_bytes(),
         );
-        hasher.finalize().to_hex().to_string()
+        Ok(hasher.finalize().to_hex().to_string())
     }
 
-    pub fn cleanup_expired(&mut self) -> anyhow::Result<()> {
-        let now = Utc::now().timestamp();
-        let mut changed = false;
+    /// Export the live allow decisions as portable JSON for another machine.
+    ///
+    /// Denies, expired decisions and session grants are left out. Entries are
+    /// sorted so the same decisions always produce the same output.
+    pub fn export_allowlist(&self) -> Result<String, PermissionStoreError> {
+        let now = self.now();
+        let mut entries: Vec<AllowlistEntry> = self
+            .permissions
+            .values()
+            .filter_map(|records| {
+                // The latest lasting decision, ignoring session grants on top of it
+                records
+                    .iter()
+                    .rfind(|record| record.session_id.is_none() && self.is_live(record, now))
+            })
+            .filter(|record| record.allowed)
+            .map(|record| AllowlistEntry {
+                tool_name: record.tool_name.clone(),
+                context_hash: record.context_hash.clone(),
+                readable_context: record.readable_context.clone(),
+                arguments: record.arguments.clone(),
+            })
+            .collect();
+        entries
+            .sort_by(|a, b| (&a.tool_name, &a.context_hash).cmp(&(&b.tool_name, &b.context_hash)));
 
-        self.permissions.retain(|_, records| {
-            records.retain(|record| record.expiry.is_none_or(|exp| exp > now));
-            changed = changed || records.is_empty();
-            !records.is_empty()
-        });
+        Ok(serde_json::to_string_pretty(&entries)?)
+    }
 
-        if changed {
-            self.save()?;
-        }
+    /// Record the decisions from [`Self::export_allowlist`] as allowed for
+    /// this user, expiring after `default_expiry` if given. Returns how many
+    /// decisions were imported.
+    pub fn import_allowlist(
+        &mut self,
+        json: &str,
+        default_expiry: Option<Duration>,
+    ) -> Result<usize, PermissionStoreError> {
+        let entries: Vec<AllowlistEntry> = serde_json::from_str(json)?;
+        let now = self.now();
+        let count = entries.len();
+
+        let mut ops = Vec::with_capacity(count);
+        for entry in entries {
+            let scope = if entry.context_hash == TOOL_WIDE_CONTEXT {
+                PermissionScope::ToolWide
+            } else {
+                PermissionScope::ContextSpecific
+            };
+            let op = PermissionLogOp::Record {
+                key: format!("{}:{}", entry.tool_name, entry.context_hash),
+                record: ToolPermissionRecord {
+                    tool_name: entry.tool_name,
+                    allowed: true,
+                    context_hash: entry.context_hash,
+                    readable_context: entry.readable_context,
+                    timestamp: now,
+                    expiry: default_expiry.map(|d| now + d.as_secs() as i64),
+                    arguments: entry.arguments,
+                    approval_count: 1,
+                    last_approved: None,
+                    scope,
+                    session_id: None,
+                },
+            };
+            ops.push(op);
+        }
+
+        self.apply_and_persist_all(ops)?;
+        Ok(count)
+    }
+
+    /// Summarize the allow decisions in effect, most frequently approved
+    /// first. Contexts whose latest live decision is a deny are left out.
+    pub fn approval_report(&self) -> Vec<ApprovalStat> {
+        let now = self.now();
+        let mut report: Vec<ApprovalStat> = self
+            .permissions
+            .values()
+            .filter_map(|records| records.iter().rfind(|record| self.is_live(record, now)))
+            .filter(|record| record.allowed)
+            .map(|record| ApprovalStat {
+                tool_name: record.tool_name.clone(),
+                readable_context: record.readable_context.clone(),
+                approval_count: record.approval_count,
+                last_approved: record.last_approved(),
+            })
+            .collect();
+        report.sort_by(|a, b| {
+            b.approval_count
+                .cmp(&a.approval_count)
+                .then(b.last_approved.cmp(&a.last_approved))
+        });
+        report
+    }
+
+    /// Remove expired records.
+    ///
+    /// Session grants from other sessions are kept, since another process
+    /// sharing the store may still be in that session. They're ignored by
+    /// lookups and pruned by [`Self::compact`].
+    pub fn cleanup_expired(&mut self) -> Result<(), PermissionStoreError> {
+        let now = self.now();
+        let mut changed = false;
+        let on_expire = self.on_expire.clone();
+
+        self.permissions.retain(|_, records| {
+            records.retain(|record| {
+                let live = record.expiry.is_none_or(|exp| exp > now);
+                if !live {
+                    if let Some(OnExpire(callback)) = &on_expire {
+                        callback(&record.tool_name, record.readable_context.as_deref());
+                    }
+                }
+                live
+            });
+            changed = changed || records.is_empty();
+            !records.is_empty()
+        });
+
+        if changed {
+            self.save()?;
+        }
         Ok(())
     }
+
+    /// Collapse redundant records, returning how many were removed.
+    ///
+    /// For every context, older records are folded into a newer live record
+    /// with the same decision that holds at least as long, absorbing their
+    /// approval counts, so a lasting decision is never replaced by a session
+    /// or timed one. Expired records and session grants from other sessions
+    /// are dropped, so effective decisions are unchanged. This
+    /// is more aggressive than [`Self::cleanup_expired`], which only removes
+    /// contexts whose records all expired.
+    pub fn compact(&mut self) -> Result<usize, PermissionStoreError> {
+        match self.format {
+            PermissionStoreFormat::Json | PermissionStoreFormat::Memory => {
+                let now = self.now();
+                let session_id = self.session_id.clone();
+                let removed = collapse_redundant_records(&mut self.permissions, |record| {
+                    record.expiry.is_none_or(|exp| exp > now) && in_session(record, &session_id)
+                });
+                if removed > 0 {
+                    self.save()?;
+                }
+                Ok(removed)
+            }
+            PermissionStoreFormat::Log => self.rewrite_log(true),
+        }
+    }
+
+    /// Rewrite the permission log so it holds one operation per live record
+    /// and rule, dropping revoked and expired entries and session grants from
+    /// other sessions.
+    ///
+    /// The log on disk is the source of truth, so it is replayed under the
+    /// lock first to pick up anything other processes appended since we loaded.
+    pub fn compact_log(&mut self) -> Result<(), PermissionStoreError> {
+        if self.format == PermissionStoreFormat::Memory {
+            return Ok(());
+        }
+        self.rewrite_log(false).map(|_| ())
+    }
+
+    // Rewrite the log from its replayed state, optionally collapsing redundant
+    // records first. Returns how many records were collapsed.
+    fn rewrite_log(&mut self, collapse: bool) -> Result<usize, PermissionStoreError> {
+        std::fs::create_dir_all(&self.permissions_dir)?;
+        let _lock = self.lock_log()?;
+
+        let mut replayed = Self {
+            max_records_per_key: self.max_records_per_key,
+            ..Self::with_dir(self.permissions_dir.clone())
+        };
+        replayed.replay_log()?;
+        let mut permissions = replayed.permissions;
+
+        let now = self.now();
+        let collapsed = if collapse {
+            collapse_redundant_records(&mut permissions, |record| self.is_live(record, now))
+        } else {
+            0
+        };
+        evict_oldest_records(&mut permissions, self.max_total_records, now);
+
+        let mut content = String::new();
+        for rule in &replayed.argument_rules {
+            let op = PermissionLogOp::Rule { rule: rule.clone() };
+            content.push_str(&self.serialize_log_op(&op)?);
+            content.push('\n');
+        }
+        for (tool_name, keys) in &replayed.ignored_arguments {
+            let op = PermissionLogOp::IgnoreArguments {
+                tool_name: tool_name.clone(),
+                keys: keys.clone(),
+            };
+            content.push_str(&self.serialize_log_op(&op)?);
+            content.push('\n');
+        }
+        for (key, records) in &permissions {
+            for record in records.iter().filter(|record| self.is_live(record, now)) {
+                let op = PermissionLogOp::Record {
+                    key: key.clone(),
+                    record: record.clone(),
+                };
+                content.push_str(&self.serialize_log_op(&op)?);
+                content.push('\n');
+            }
+        }
+
+        let path = self.log_path();
+        let temp_path = path.with_extension("jsonl.tmp");
+        std::fs::write(&temp_path, &content)?;
+        std::fs::rename(temp_path, path)?;
+
+        permissions.retain(|_, records| {
+            records.retain(|record| self.is_live(record, now));
+            !records.is_empty()
+        });
+        self.permissions = permissions;
+        self.argument_rules = replayed.argument_rules;
+        self.ignored_arguments = replayed.ignored_arguments;
+
+        Ok(collapsed)
+    }
+
+    fn log_path(&self) -> PathBuf {
+        self.permissions_dir.join(PERMISSIONS_LOG_FILE)
+    }
+
+    // Appends and compactions lock a sidecar file rather than the log itself,
+    // since compaction replaces the log with a new file
+    fn lock_log(&self) -> Result<File, PermissionStoreError> {
+        let lock_file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.log_path().with_extension("jsonl.lock"))?;
+        lock_file
+            .lock_exclusive()
+            .map_err(|e| PermissionStoreError::Lock(e.to_string()))?;
+        Ok(lock_file)
+    }
+
+    fn append_log_op(&self, op: &PermissionLogOp) -> Result<(), PermissionStoreError> {
+        std::fs::create_dir_all(&self.permissions_dir)?;
+        let _lock = self.lock_log()?;
+
+        let mut line = self.serialize_log_op(op)?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(self.log_path())?;
+        file.write_all(line.as_bytes())?;
+        file.sync_all()?;
+
+        Ok(())
+    }
+
+    fn serialize_log_op(&self, op: &PermissionLogOp) -> serde_json::Result<String> {
+        let mut value = serde_json::to_value(op)?;
+        if let Some(record) = value.get_mut("record") {
+            format_record_timestamps(record, self.timestamp_format);
+        }
+        serde_json::to_string(&value)
+    }
+
+    // Replay the log into memory, returning how many operations it held
+    fn replay_log(&mut self) -> Result<usize, PermissionStoreError> {
+        self.permissions.clear();
+        self.argument_rules.clear();
+        self.ignored_arguments.clear();
+
+        let path = self.log_path();
+        if !path.exists() {
+            return Ok(0);
+        }
+
+        let reader = BufReader::new(File::open(path)?);
+        let mut op_count = 0;
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            self.apply_log_op(serde_json::from_str(&line)?);
+            op_count += 1;
+        }
+
+        Ok(op_count)
+    }
+}
+
+// The key of the records that apply to every call of a tool
+fn tool_wide_key(tool_name: &str) -> String {
+    format!("{}:{}", tool_name, TOOL_WIDE_CONTEXT)
+}
+
+fn app_config_dir(args: AppStrategyArgs) -> PathBuf {
+    choose_app_strategy(args)
+        .map(|strategy| strategy.config_dir())
+        .unwrap_or_else(|_| PathBuf::from(".config/goose"))
+}
+
+// Drop the oldest records of a single key by timestamp until at most
+// `max_records` remain, keeping the order of the rest
+fn prune_oldest_in_key(records: &mut Vec<ToolPermissionRecord>, max_records: usize) {
+    while records.len() > max_records.max(1) {
+        let oldest = records
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, record)| record.timestamp)
+            .map(|(i, _)| i)
+            .expect("records is not empty");
+        records.remove(oldest);
+    }
+}
+
+// Drop records until at most `max_records` remain, removing expired records
+// first and then the oldest by timestamp. Returns how many were removed.
+fn evict_oldest_records(
+    permissions: &mut HashMap<String, Vec<ToolPermissionRecord>>,
+    max_records: usize,
+    now: i64,
+) -> usize {
+    let total: usize = permissions.values().map(Vec::len).sum();
+    if total <= max_records {
+        return 0;
+    }
+
+    // Rank every record, live before expired and newest first, and keep the top ones
+    let mut ranked: Vec<(bool, i64, &String, usize)> = permissions
+        .iter()
+        .flat_map(|(key, records)| {
+            records.iter().enumerate().map(move |(index, record)| {
+                let live = record.expiry.is_none_or(|exp| exp > now);
+                (live, record.last_approved(), key, index)
+            })
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)));
+    let keep: HashSet<(String, usize)> = ranked
+        .into_iter()
+        .take(max_records)
+        .map(|(_, _, key, index)| (key.clone(), index))
+        .collect();
+
+    for (key, records) in permissions.iter_mut() {
+        let mut index = 0;
+        records.retain(|_| {
+            let kept = keep.contains(&(key.clone(), index));
+            index += 1;
+            kept
+        });
+    }
+    permissions.retain(|_, records| !records.is_empty());
+
+    total - max_records
+}
+
+// Drop the records `live` rejects and fold every other record into a newer
+// one with the same decision that holds at least as long, keeping the rest in
+// their original order. The older record could never be the latest live one,
+// so effective decisions are unchanged. Returns how many records were removed.
+fn collapse_redundant_records(
+    permissions: &mut HashMap<String, Vec<ToolPermissionRecord>>,
+    live: impl Fn(&ToolPermissionRecord) -> bool,
+) -> usize {
+    let mut removed = 0;
+    for records in permissions.values_mut() {
+        let before = records.len();
+        let mut kept: Vec<ToolPermissionRecord> = Vec::new();
+        for record in records.drain(..).rev().filter(|record| live(record)) {
+            match kept.iter_mut().find(|newer| outlasts(newer, &record)) {
+                Some(newer) => newer.approval_count += record.approval_count,
+                None => kept.push(record),
+            }
+        }
+        kept.reverse();
+        removed += before - kept.len();
+        *records = kept;
+    }
+    permissions.retain(|_, records| !records.is_empty());
+    removed
+}
+
+// Whether `newer` makes the same decision as `older` for the same context and
+// session, and is in effect whenever `older` is. A lasting record never
+// outlasts a timed one, so it isn't folded into it.
+fn outlasts(newer: &ToolPermissionRecord, older: &ToolPermissionRecord) -> bool {
+    newer.allowed == older.allowed
+        && newer.context_hash == older.context_hash
+        && newer.session_id == older.session_id
+        && match (newer.expiry, older.expiry) {
+            (None, None) => true,
+            (Some(newer_exp), Some(older_exp)) => newer_exp >= older_exp,
+            _ => false,
+        }
+}
+
+// Jaccard similarity of the top-level key/value pairs of two argument sets;
+// arguments that aren't objects are only similar when equal
+fn argument_similarity(a: &Value, b: &Value) -> f64 {
+    let (Value::Object(a), Value::Object(b)) = (a, b) else {
+        return if a == b { 1.0 } else { 0.0 };
+    };
+    let union: HashSet<&String> = a.keys().chain(b.keys()).collect();
+    if union.is_empty() {
+        return 1.0;
+    }
+    let shared = a
+        .iter()
+        .filter(|(key, value)| b.get(*key) == Some(value))
+        .count();
+    shared as f64 / union.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mcp_core::tool::ToolCall;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn tool_request(name: &str, arguments: serde_json::Value) -> ToolRequest {
+        ToolRequest {
+            id: "request".to_string(),
+            tool_call: Ok(ToolCall::new(name, arguments)),
+        }
+    }
+
+    fn store_in(dir: &TempDir, format: PermissionStoreFormat) -> ToolPermissionStore {
+        ToolPermissionStore::load_from_dir(dir.path().to_path_buf(), format).unwrap()
+    }
+
+    fn record_op(
+        store: &ToolPermissionStore,
+        request: &ToolRequest,
+        allowed: bool,
+    ) -> PermissionLogOp {
+        let tool_call = request.tool_call.as_ref().unwrap();
+        let context_hash = store.hash_tool_context(request).unwrap();
+        PermissionLogOp::Record {
+            key: format!("{}:{}", tool_call.name, context_hash),
+            record: ToolPermissionRecord {
+                tool_name: tool_call.name.clone(),
+                allowed,
+                context_hash,
+                readable_context: Some(request.to_readable_string()),
+                timestamp: Utc::now().timestamp(),
+                expiry: None,
+                arguments: Some(tool_call.arguments.clone()),
+                approval_count: 1,
+                last_approved: None,
+                scope: PermissionScope::ContextSpecific,
+                session_id: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_log_replay_reconstructs_state() {
+        let dir = TempDir::new().unwrap();
+        let store = store_in(&dir, PermissionStoreFormat::Log);
+
+        let shell = tool_request("developer__shell", json!({"command": "ls"}));
+        let editor = tool_request("developer__text_editor", json!({"command": "view"}));
+        let fetch = tool_request("web__fetch", json!({"url": "https://example.com"}));
+
+        let shell_op = record_op(&store, &shell, true);
+        let shell_key = match &shell_op {
+            PermissionLogOp::Record { key, .. } => key.clone(),
+            _ => unreachable!(),
+        };
+
+        store.append_log_op(&shell_op).unwrap();
+        store
+            .append_log_op(&record_op(&store, &editor, true))
+            .unwrap();
+        store
+            .append_log_op(&record_op(&store, &editor, false))
+            .unwrap();
+        store
+            .append_log_op(&record_op(&store, &fetch, true))
+            .unwrap();
+        store
+            .append_log_op(&PermissionLogOp::Revoke { key: shell_key })
+            .unwrap();
+
+        let replayed = store_in(&dir, PermissionStoreFormat::Log);
+        assert_eq!(replayed.check_permission(&shell), None);
+        assert_eq!(replayed.check_permission(&editor), Some(false));
+        assert_eq!(replayed.check_permission(&fetch), Some(true));
+    }
+
+    #[test]
+    fn test_log_records_survive_reload() {
+        let dir = TempDir::new().unwrap();
+        let mut store = store_in(&dir, PermissionStoreFormat::Log);
+        let request = tool_request("developer__shell", json!({"command": "ls"}));
+
+        store.record_permission(&request, true, None).unwrap();
+
+        let reloaded = store_in(&dir, PermissionStoreFormat::Log);
+        assert_eq!(reloaded.check_permission(&request), Some(true));
+        assert!(!dir.path().join(PERMISSIONS_FILE).exists());
+    }
+
+    #[test]
+    fn test_log_compaction_preserves_state() {
+        let dir = TempDir::new().unwrap();
+        let mut store = store_in(&dir, PermissionStoreFormat::Log);
+
+        let shell = tool_request("developer__shell", json!({"command": "ls"}));
+        let editor = tool_request("developer__text_editor", json!({"command": "view"}));
+
+        store.record_permission(&shell, true, None).unwrap();
+        store.record_permission(&editor, true, None).unwrap();
+        store.record_permission(&editor, false, None).unwrap();
+        let key = format!(
+            "developer__shell:{}",
+            store.hash_tool_context(&shell).unwrap()
+        );
+        store
+            .append_log_op(&PermissionLogOp::Revoke { key })
+            .unwrap();
+
+        let log_lines = |dir: &TempDir| {
+            std::fs::read_to_string(dir.path().join(PERMISSIONS_LOG_FILE))
+                .unwrap()
+                .lines()
+                .count()
+        };
+        assert_eq!(log_lines(&dir), 4);
+
+        store.compact_log().unwrap();
+        assert_eq!(log_lines(&dir), 2);
+
+        let compacted = store_in(&dir, PermissionStoreFormat::Log);
+        assert_eq!(compacted.check_permission(&shell), None);
+        assert_eq!(compacted.check_permission(&editor), Some(false));
+    }
+
+    #[test]
+    fn test_log_is_only_appended_until_compacted() {
+        let dir = TempDir::new().unwrap();
+        let log_path = dir.path().join(PERMISSIONS_LOG_FILE);
+        let mut store = store_in(&dir, PermissionStoreFormat::Log);
+        let ls = tool_request("developer__shell", json!({"command": "ls"}));
+        let pwd = tool_request("developer__shell", json!({"command": "pwd"}));
+        let cat = tool_request("developer__shell", json!({"command": "cat a.txt"}));
+
+        store.record_permission(&ls, true, None).unwrap();
+        // Expires as soon as it's recorded
+        store
+            .record_permission(&pwd, true, Some(Duration::ZERO))
+            .unwrap();
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        assert_eq!(log.lines().count(), 2);
+
+        // Saving and cleaning up, including on load, leave the log alone
+        store.cleanup_expired().unwrap();
+        store.save().unwrap();
+        let mut reloaded = store_in(&dir, PermissionStoreFormat::Log);
+        assert_eq!(std::fs::read_to_string(&log_path).unwrap(), log);
+        assert_eq!(reloaded.check_permission(&ls), Some(true));
+        assert_eq!(reloaded.check_permission(&pwd), None);
+
+        // New decisions are appended after what was there
+        reloaded.record_permission(&cat, true, None).unwrap();
+        let appended = std::fs::read_to_string(&log_path).unwrap();
+        assert!(appended.starts_with(&log));
+        assert_eq!(appended.lines().count(), 3);
+
+        // Only compaction rewrites it, dropping the expired decision
+        reloaded.compact_log().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&log_path).unwrap().lines().count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_expiring_within() {
+        let dir = TempDir::new().unwrap();
+        let mut store = store_in(&dir, PermissionStoreFormat::Json);
+        let threshold = Duration::from_secs(60);
+
+        let long_lived = tool_request("developer__shell", json!({"command": "ls"}));
+        store
+            .record_permission(&long_lived, true, Some(Duration::from_secs(3600)))
+            .unwrap();
+        assert!(!store.expiring_within(&long_lived, threshold));
+
+        let short_lived = tool_request("developer__shell", json!({"command": "pwd"}));
+        store
+            .record_permission(&short_lived, true, Some(Duration::from_secs(30)))
+            .unwrap();
+        assert!(store.expiring_within(&short_lived, threshold));
+
+        let at_threshold = tool_request("developer__shell", json!({"command": "whoami"}));
+        store
+            .record_permission(&at_threshold, true, Some(threshold))
+            .unwrap();
+        assert!(store.expiring_within(&at_threshold, threshold));
+
+        let permanent = tool_request("developer__shell", json!({"command": "date"}));
+        store.record_permission(&permanent, true, None).unwrap();
+        assert!(!store.expiring_within(&permanent, threshold));
+
+        let expired = tool_request("developer__shell", json!({"command": "uptime"}));
+        let context_hash = store.hash_tool_context(&expired).unwrap();
+        let key = format!("developer__shell:{}", context_hash);
+        store
+            .permissions
+            .entry(key)
+            .or_default()
+            .push(ToolPermissionRecord {
+                tool_name: "developer__shell".to_string(),
+                allowed: true,
+                context_hash,
+                readable_context: None,
+                timestamp: Utc::now().timestamp() - 120,
+                expiry: Some(Utc::now().timestamp() - 60),
+                arguments: None,
+                approval_count: 1,
+                last_approved: None,
+                scope: PermissionScope::ContextSpecific,
+                session_id: None,
+            });
+        assert_eq!(store.check_permission(&expired), None);
+        assert!(!store.expiring_within(&expired, threshold));
+    }
+
+    #[test]
+    fn test_global_record_limit_evicts_oldest() {
+        let dir = TempDir::new().unwrap();
+        let mut store = store_in(&dir, PermissionStoreFormat::Json).with_max_total_records(3);
+        let now = Utc::now().timestamp();
+
+        let requests: Vec<ToolRequest> = (0..5)
+            .map(|i| tool_request("developer__shell", json!({"command": format!("cmd{}", i)})))
+            .collect();
+        for (i, request) in requests.iter().enumerate() {
+            let context_hash = store.hash_tool_context(request).unwrap();
+            let key = format!("developer__shell:{}", context_hash);
+            // The newest record has already expired, so it goes before any live one
+            let expiry = (i == 4).then_some(now - 1);
+            store
+                .permissions
+                .entry(key)
+                .or_default()
+                .push(ToolPermissionRecord {
+                    tool_name: "developer__shell".to_string(),
+                    allowed: true,
+                    context_hash,
+                    readable_context: None,
+                    timestamp: now - 100 + i as i64,
+                    expiry,
+                    arguments: None,
+                    approval_count: 1,
+                    last_approved: None,
+                    scope: PermissionScope::ContextSpecific,
+                    session_id: None,
+                });
+        }
+
+        store.save().unwrap();
+
+        let reloaded = store_in(&dir, PermissionStoreFormat::Json);
+        assert_eq!(reloaded.check_permission(&requests[0]), None);
+        assert_eq!(reloaded.check_permission(&requests[1]), Some(true));
+        assert_eq!(reloaded.check_permission(&requests[2]), Some(true));
+        assert_eq!(reloaded.check_permission(&requests[3]), Some(true));
+        assert_eq!(
+            reloaded.permissions.values().map(Vec::len).sum::<usize>(),
+            3
+        );
+
+        // Recording past the cap drops the least recently recorded decision
+        store.record_permission(&requests[0], false, None).unwrap();
+        assert_eq!(store.check_permission(&requests[0]), Some(false));
+        assert_eq!(store.check_permission(&requests[1]), None);
+        assert_eq!(store.check_permission(&requests[3]), Some(true));
+    }
+
+    #[test]
+    fn test_policy_applies_after_user_decisions() {
+        let dir = TempDir::new().unwrap();
+        let policy_path = dir.path().join("goose_permissions_policy.json");
+        let policy_content = r#"{"allow": ["web__fetch"], "deny": ["developer__shell"]}"#;
+        std::fs::write(&policy_path, policy_content).unwrap();
+
+        let mut store = store_in(&dir, PermissionStoreFormat::Json);
+        store.policy = Some(PermissionPolicy::load(&policy_path).unwrap());
+
+        let shell_ls = tool_request("developer__shell", json!({"command": "ls"}));
+        let shell_rm = tool_request("developer__shell", json!({"command": "rm -rf /"}));
+        let fetch = tool_request("web__fetch", json!({"url": "https://example.com"}));
+        let editor = tool_request("developer__text_editor", json!({"command": "view"}));
+
+        // The policy covers tools the user hasn't decided on
+        assert_eq!(store.check_permission(&shell_rm), Some(false));
+        assert_eq!(store.check_permission(&fetch), Some(true));
+        assert_eq!(store.check_permission(&editor), None);
+
+        // A user allow coexists with the policy deny for other contexts
+        store.record_permission(&shell_ls, true, None).unwrap();
+        assert_eq!(store.check_permission(&shell_ls), Some(true));
+        assert_eq!(store.check_permission(&shell_rm), Some(false));
+
+        // Saving never touches the policy file or copies it into the store
+        assert_eq!(
+            std::fs::read_to_string(&policy_path).unwrap(),
+            policy_content
+        );
+        let saved = std::fs::read_to_string(dir.path().join(PERMISSIONS_FILE)).unwrap();
+        assert!(!saved.contains("web__fetch"));
+    }
+
+    #[test]
+    fn test_argument_rules() {
+        let dir = TempDir::new().unwrap();
+        let mut store = store_in(&dir, PermissionStoreFormat::Json);
+        store
+            .add_argument_rule(
+                "developer__text_editor",
+                vec![ArgumentPredicate::new("command", json!("view"))],
+                true,
+            )
+            .unwrap();
+        store
+            .add_argument_rule(
+                "developer__text_editor",
+                vec![
+                    ArgumentPredicate::new("command", json!("view")),
+                    ArgumentPredicate::new("options.path", json!("/etc/shadow")),
+                ],
+                false,
+            )
+            .unwrap();
+
+        let view = tool_request(
+            "developer__text_editor",
+            json!({"command": "view", "options": {"path": "README.md"}}),
+        );
+        let view_shadow = tool_request(
+            "developer__text_editor",
+            json!({"command": "view", "options": {"path": "/etc/shadow"}}),
+        );
+        let write = tool_request(
+            "developer__text_editor",
+            json!({"command": "write", "options": {"path": "README.md"}}),
+        );
+        let shell = tool_request("developer__shell", json!({"command": "view"}));
+
+        assert_eq!(store.check_permission(&view), Some(true));
+        assert_eq!(store.check_permission(&view_shadow), Some(false));
+        assert_eq!(store.check_permission(&write), None);
+        assert_eq!(store.check_permission(&shell), None);
+
+        // An exact recorded decision takes precedence over rules
+        store.record_permission(&view, false, None).unwrap();
+        assert_eq!(store.check_permission(&view), Some(false));
+
+        for format in [PermissionStoreFormat::Json, PermissionStoreFormat::Log] {
+            let dir = TempDir::new().unwrap();
+            let mut store = store_in(&dir, format);
+            store
+                .add_argument_rule(
+                    "developer__text_editor",
+                    vec![ArgumentPredicate::new("command", json!("view"))],
+                    true,
+                )
+                .unwrap();
+            let reloaded = store_in(&dir, format);
+            assert_eq!(reloaded.check_permission(&view), Some(true));
+            assert_eq!(reloaded.check_permission(&write), None);
+        }
+    }
+
+    #[test]
+    fn test_on_expire_fires_for_expired_records() {
+        let dir = TempDir::new().unwrap();
+        let expired_tools = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = expired_tools.clone();
+        let mut store = store_in(&dir, PermissionStoreFormat::Json).with_on_expire(
+            move |tool_name, readable_context| {
+                assert!(readable_context.is_some());
+                seen.lock().unwrap().push(tool_name.to_string());
+            },
+        );
+
+        let expired = tool_request("developer__shell", json!({"command": "uptime"}));
+        let mut op = record_op(&store, &expired, true);
+        if let PermissionLogOp::Record { record, .. } = &mut op {
+            record.expiry = Some(Utc::now().timestamp() - 60);
+        }
+        store.apply_log_op(op);
+
+        let live = tool_request("developer__text_editor", json!({"path": "a.txt"}));
+        store
+            .record_permission(&live, true, Some(Duration::from_secs(3600)))
+            .unwrap();
+
+        store.cleanup_expired().unwrap();
+        store.cleanup_expired().unwrap();
+
+        assert_eq!(*expired_tools.lock().unwrap(), vec!["developer__shell"]);
+        assert_eq!(store.check_permission(&live), Some(true));
+    }
+
+    #[test]
+    fn test_decide_detects_argument_drift() {
+        let dir = TempDir::new().unwrap();
+        let mut store = store_in(&dir, PermissionStoreFormat::Json).with_drift_threshold(0.5);
+
+        let approved = tool_request(
+            "developer__text_editor",
+            json!({"command": "write", "path": "notes.txt", "mode": "append"}),
+        );
+        store.record_permission(&approved, true, None).unwrap();
+        assert_eq!(store.decide(&approved), Decision::Allow);
+
+        let drifted = tool_request(
+            "developer__text_editor",
+            json!({"command": "write", "path": "notes.txt", "mode": "overwrite"}),
+        );
+        assert_eq!(store.decide(&drifted), Decision::ReaskOnDrift);
+        assert_eq!(store.check_permission(&drifted), None);
+
+        let different = tool_request(
+            "developer__text_editor",
+            json!({"command": "view", "path": "other.txt"}),
+        );
+        assert_eq!(store.decide(&different), Decision::Unknown);
+
+        // A similar call to another tool isn't drift
+        let other_tool = tool_request(
+            "developer__shell",
+            json!({"command": "write", "path": "notes.txt", "mode": "overwrite"}),
+        );
+        assert_eq!(store.decide(&other_tool), Decision::Unknown);
+
+        // Without a threshold drifted calls are simply unknown
+        let plain = store_in(&dir, PermissionStoreFormat::Json);
+        assert_eq!(plain.decide(&approved), Decision::Allow);
+        assert_eq!(plain.decide(&drifted), Decision::Unknown);
+    }
+
+    #[test]
+    fn test_repeated_approvals_are_counted() {
+        let dir = TempDir::new().unwrap();
+        let mut store = store_in(&dir, PermissionStoreFormat::Json);
+
+        let frequent = tool_request("developer__shell", json!({"command": "ls"}));
+        for _ in 0..3 {
+            store.record_permission(&frequent, true, None).unwrap();
+        }
+        let rare = tool_request("developer__shell", json!({"command": "pwd"}));
+        store.record_permission(&rare, true, None).unwrap();
+
+        let key = format!(
+            "developer__shell:{}",
+            store.hash_tool_context(&frequent).unwrap()
+        );
+        assert_eq!(store.permissions[&key].len(), 1);
+        assert_eq!(store.permissions[&key][0].approval_count, 3);
+
+        let report = store_in(&dir, PermissionStoreFormat::Json).approval_report();
+        let counts: Vec<u32> = report.iter().map(|stat| stat.approval_count).collect();
+        assert_eq!(counts, vec![3, 1]);
+        assert_eq!(report[0].tool_name, "developer__shell");
+        assert_eq!(
+            report[0].readable_context,
+            Some(frequent.to_readable_string())
+        );
+
+        // A different decision starts a new record, and a deny hides the
+        // approvals before it from the report
+        store.record_permission(&frequent, false, None).unwrap();
+        assert_eq!(store.permissions[&key].len(), 2);
+        assert_eq!(store.check_permission(&frequent), Some(false));
+        let report = store.approval_report();
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].readable_context, Some(rare.to_readable_string()));
+
+        store.record_permission(&frequent, true, None).unwrap();
+        let report = store_in(&dir, PermissionStoreFormat::Json).approval_report();
+        let counts: Vec<u32> = report.iter().map(|stat| stat.approval_count).collect();
+        assert_eq!(counts, vec![1, 1]);
+    }
+
+    #[test]
+    fn test_repeated_approvals_are_counted_in_log() {
+        let dir = TempDir::new().unwrap();
+        let mut store = store_in(&dir, PermissionStoreFormat::Log);
+        let request = tool_request("developer__shell", json!({"command": "ls"}));
+        for _ in 0..4 {
+            store.record_permission(&request, true, None).unwrap();
+        }
+
+        let mut reloaded = store_in(&dir, PermissionStoreFormat::Log);
+        assert_eq!(reloaded.approval_report()[0].approval_count, 4);
+        reloaded.compact_log().unwrap();
+        let compacted = store_in(&dir, PermissionStoreFormat::Log);
+        assert_eq!(compacted.approval_report()[0].approval_count, 4);
+    }
+
+    #[test]
+    fn test_timed_repeat_keeps_lasting_decision() {
+        use std::sync::atomic::{AtomicI64, Ordering};
+
+        let dir = TempDir::new().unwrap();
+        let now = Arc::new(AtomicI64::new(1_700_000_000));
+        let clock = now.clone();
+        let mut store = store_in(&dir, PermissionStoreFormat::Json)
+            .with_clock(move || clock.load(Ordering::SeqCst));
+        let request = tool_request("developer__shell", json!({"command": "ls"}));
+        let key = format!(
+            "developer__shell:{}",
+            store.hash_tool_context(&request).unwrap()
+        );
+
+        store.record_permission(&request, true, None).unwrap();
+        store
+            .record_permission(&request, true, Some(Duration::from_secs(60)))
+            .unwrap();
+        assert_eq!(store.permissions[&key].len(), 2);
+        assert_eq!(store.permissions[&key][0].expiry(), None);
+
+        // Once the timed approval lapses the lasting one still applies
+        now.fetch_add(61, Ordering::SeqCst);
+        assert_eq!(store.check_permission(&request), Some(true));
+
+        // Importing with a default expiry doesn't shorten it either
+        let allowlist = store.export_allowlist().unwrap();
+        store
+            .import_allowlist(&allowlist, Some(Duration::from_secs(60)))
+            .unwrap();
+        now.fetch_add(61, Ordering::SeqCst);
+        assert_eq!(store.check_permission(&request), Some(true));
+    }
+
+    #[test]
+    fn test_timestamp_formats_round_trip() {
+        let legacy = r#"{
+            "permissions": {
+                "developer__shell:abc": [{
+                    "tool_name": "developer__shell",
+                    "allowed": true,
+                    "context_hash": "abc",
+                    "timestamp": 1735689600,
+                    "expiry": 4102444800
+                }]
+            },
+            "version": 1
+        }"#;
+        let parsed: ToolPermissionStore = serde_json::from_str(legacy).unwrap();
+        let record = &parsed.permissions["developer__shell:abc"][0];
+        assert_eq!(record.timestamp, 1735689600);
+        assert_eq!(record.expiry, Some(4102444800));
+        assert_eq!(record.last_approved, None);
+
+        let rfc3339 = r#"{
+            "permissions": {
+                "developer__shell:abc": [{
+                    "tool_name": "developer__shell",
+                    "allowed": true,
+                    "context_hash": "abc",
+                    "timestamp": "2025-01-01T00:00:00Z",
+                    "expiry": "2100-01-01T00:00:00+00:00"
+                }]
+            },
+            "version": 2
+        }"#;
+        let parsed: ToolPermissionStore = serde_json::from_str(rfc3339).unwrap();
+        let record = &parsed.permissions["developer__shell:abc"][0];
+        assert_eq!(record.timestamp, 1735689600);
+        assert_eq!(record.expiry, Some(4102444800));
+
+        for format in [PermissionStoreFormat::Json, PermissionStoreFormat::Log] {
+            let dir = TempDir::new().unwrap();
+            let mut store = store_in(&dir, format).with_timestamp_format(TimestampFormat::Rfc3339);
+            let request = tool_request("developer__shell", json!({"command": "ls"}));
+            store
+                .record_permission(&request, true, Some(Duration::from_secs(3600)))
+                .unwrap();
+            store.save().unwrap();
+
+            let file = match format {
+                PermissionStoreFormat::Json => PERMISSIONS_FILE,
+                PermissionStoreFormat::Log => PERMISSIONS_LOG_FILE,
+                PermissionStoreFormat::Memory => unreachable!(),
+            };
+            let content = std::fs::read_to_string(dir.path().join(file)).unwrap();
+            let year = Utc::now().format("\"%Y-").to_string();
+            assert!(content.contains(&year), "{}", content);
+
+            let reloaded = store_in(&dir, format);
+            assert_eq!(reloaded.check_permission(&request), Some(true));
+            assert!(!reloaded.expiring_within(&request, Duration::from_secs(60)));
+        }
+    }
+
+    #[test]
+    fn test_legacy_store_is_upgraded_on_save() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join(PERMISSIONS_FILE),
+            r#"{"permissions": {}, "version": 1}"#,
+        )
+        .unwrap();
+
+        let mut store = store_in(&dir, PermissionStoreFormat::Json);
+        store.save().unwrap();
+
+        let content = std::fs::read_to_string(dir.path().join(PERMISSIONS_FILE)).unwrap();
+        let saved: Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(saved["version"], json!(STORE_VERSION));
+    }
+
+    #[test]
+    fn test_expired_records_visible_to_debug_views() {
+        let dir = TempDir::new().unwrap();
+        let mut store = store_in(&dir, PermissionStoreFormat::Json);
+
+        let expired = tool_request("developer__shell", json!({"command": "uptime"}));
+        let mut op = record_op(&store, &expired, true);
+        if let PermissionLogOp::Record { record, .. } = &mut op {
+            record.expiry = Some(Utc::now().timestamp() - 60);
+        }
+        store.apply_log_op(op);
+
+        assert_eq!(store.check_permission(&expired), None);
+        assert_eq!(store.check_permission_ignoring_expiry(&expired), Some(true));
+
+        let records: Vec<_> = store.all_records_including_expired().collect();
+        assert_eq!(records.len(), 1);
+        let (key, record) = records[0];
+        assert!(key.starts_with("developer__shell:"));
+        assert_eq!(record.tool_name(), "developer__shell");
+        assert!(record.expiry().unwrap() < Utc::now().timestamp());
+
+        // Neither view cleans up the expired record
+        assert_eq!(store.all_records_including_expired().count(), 1);
+
+        let unknown = tool_request("developer__shell", json!({"command": "ls"}));
+        assert_eq!(store.check_permission_ignoring_expiry(&unknown), None);
+    }
+
+    #[test]
+    fn test_ignored_arguments_share_approval() {
+        for format in [PermissionStoreFormat::Json, PermissionStoreFormat::Log] {
+            let dir = TempDir::new().unwrap();
+            let mut store = store_in(&dir, format);
+            store
+                .set_ignored_arguments("web__search", vec!["limit".to_string()])
+                .unwrap();
+
+            let without_limit = tool_request("web__search", json!({"query": "rust"}));
+            let with_limit = tool_request("web__search", json!({"query": "rust", "limit": 10}));
+            let other_query = tool_request("web__search", json!({"query": "go", "limit": 10}));
+            store.record_permission(&without_limit, true, None).unwrap();
+
+            assert_eq!(store.check_permission(&with_limit), Some(true));
+            assert_eq!(store.check_permission(&other_query), None);
+
+            // The ignore list is persisted with the store
+            let mut reloaded = store_in(&dir, format);
+            assert_eq!(reloaded.check_permission(&with_limit), Some(true));
+            reloaded.save().unwrap();
+            let compacted = store_in(&dir, format);
+            assert_eq!(compacted.check_permission(&with_limit), Some(true));
+
+            // Other tools still hash every argument
+            let mut store = compacted;
+            let fetch = tool_request("web__fetch", json!({"url": "a"}));
+            store.record_permission(&fetch, true, None).unwrap();
+            let fetch_with_limit = tool_request("web__fetch", json!({"url": "a", "limit": 10}));
+            assert_eq!(store.check_permission(&fetch_with_limit), None);
+        }
+    }
+
+    #[test]
+    fn test_structured_errors() {
+        let dir = TempDir::new().unwrap();
+        // A file where the permissions directory should be can't be written into
+        let blocker = dir.path().join("not_a_dir");
+        std::fs::write(&blocker, "").unwrap();
+        let mut store = ToolPermissionStore {
+            permissions_dir: blocker.join("permissions"),
+            ..ToolPermissionStore::new()
+        };
+
+        let request = tool_request("developer__shell", json!({"command": "ls"}));
+        assert!(matches!(
+            store.record_permission(&request, true, None),
+            Err(PermissionStoreError::Io(_))
+        ));
+
+        let invalid = ToolRequest {
+            id: "request".to_string(),
+            tool_call: Err(mcp_core::handler::ToolError::InvalidParameters(
+                "bad arguments".to_string(),
+            )),
+        };
+        assert!(matches!(
+            store.record_permission(&invalid, true, None),
+            Err(PermissionStoreError::InvalidRequest(_))
+        ));
+        // Checking an invalid request finds no decision rather than panicking
+        assert_eq!(store.check_permission(&invalid), None);
+        assert_eq!(store.check_permission_ignoring_expiry(&invalid), None);
+        assert_eq!(store.decide(&invalid), Decision::Unknown);
+        assert!(!store.expiring_within(&invalid, Duration::from_secs(60)));
+        assert!(matches!(
+            store.revoke_context(&invalid),
+            Err(PermissionStoreError::InvalidRequest(_))
+        ));
+
+        std::fs::write(dir.path().join(PERMISSIONS_FILE), "not json").unwrap();
+        assert!(matches!(
+            ToolPermissionStore::load_from_dir(
+                dir.path().to_path_buf(),
+                PermissionStoreFormat::Json
+            ),
+            Err(PermissionStoreError::Serde(_))
+        ));
+
+        // Errors still convert into anyhow for callers that don't match on them
+        let error: anyhow::Error = store
+            .record_permission(&request, true, None)
+            .unwrap_err()
+            .into();
+        assert!(error.to_string().contains("tool permissions file"));
+    }
+
+    #[test]
+    fn test_mock_clock_expires_without_sleeping() {
+        use std::sync::atomic::{AtomicI64, Ordering};
+
+        let dir = TempDir::new().unwrap();
+        let now = Arc::new(AtomicI64::new(1_700_000_000));
+        let clock = now.clone();
+        let mut store = store_in(&dir, PermissionStoreFormat::Json)
+            .with_clock(move || clock.load(Ordering::SeqCst));
+
+        let request = tool_request("developer__shell", json!({"command": "ls"}));
+        store
+            .record_permission(&request, true, Some(Duration::from_secs(60)))
+            .unwrap();
+        assert_eq!(store.check_permission(&request), Some(true));
+        assert!(!store.expiring_within(&request, Duration::from_secs(30)));
+
+        now.fetch_add(45, Ordering::SeqCst);
+        assert!(store.expiring_within(&request, Duration::from_secs(30)));
+
+        now.fetch_add(16, Ordering::SeqCst);
+        assert_eq!(store.check_permission(&request), None);
+        store.cleanup_expired().unwrap();
+        assert_eq!(store.all_records_including_expired().count(), 0);
+    }
+
+    #[test]
+    fn test_max_file_bytes_evicts_oldest() {
+        let dir = TempDir::new().unwrap();
+        let now = Arc::new(std::sync::atomic::AtomicI64::new(1_700_000_000));
+        let clock = now.clone();
+        let max_file_bytes = 2048;
+        let mut store = store_in(&dir, PermissionStoreFormat::Json)
+            .with_clock(move || clock.load(std::sync::atomic::Ordering::SeqCst))
+            .with_max_file_bytes(max_file_bytes);
+
+        let requests: Vec<ToolRequest> = (0..20)
+            .map(|i| tool_request("developer__shell", json!({"command": format!("cmd{}", i)})))
+            .collect();
+        for request in &requests {
+            now.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            store.record_permission(request, true, None).unwrap();
+        }
+
+        let size = std::fs::metadata(dir.path().join(PERMISSIONS_FILE))
+            .unwrap()
+            .len() as usize;
+        assert!(size <= max_file_bytes, "file is {} bytes", size);
+
+        // The newest decisions survive while the oldest were dropped
+        let reloaded = store_in(&dir, PermissionStoreFormat::Json);
+        assert_eq!(reloaded.check_permission(&requests[19]), Some(true));
+        assert_eq!(reloaded.check_permission(&requests[0]), None);
+
+        // A single record that can't fit is an error
+        let tiny_dir = TempDir::new().unwrap();
+        let mut tiny = store_in(&tiny_dir, PermissionStoreFormat::Json).with_max_file_bytes(10);
+        assert!(matches!(
+            tiny.record_permission(&requests[0], true, None),
+            Err(PermissionStoreError::FileTooLarge { max: 10, .. })
+        ));
+    }
+
+    #[test]
+    fn test_max_file_bytes_below_one_record_is_an_error() {
+        let dir = TempDir::new().unwrap();
+        let mut store = store_in(&dir, PermissionStoreFormat::Json);
+        // Room for an empty store but not for a single record
+        let max_file_bytes = store.to_json().unwrap().len() + 10;
+        let requests: Vec<ToolRequest> = (0..3)
+            .map(|i| tool_request("developer__shell", json!({"command": format!("cmd{}", i)})))
+            .collect();
+        for request in &requests[..2] {
+            store.record_permission(request, true, None).unwrap();
+        }
+        let path = dir.path().join(PERMISSIONS_FILE);
+        let on_disk = std::fs::read_to_string(&path).unwrap();
+
+        let mut capped =
+            store_in(&dir, PermissionStoreFormat::Json).with_max_file_bytes(max_file_bytes);
+        assert!(matches!(
+            capped.record_permission(&requests[2], true, None),
+            Err(PermissionStoreError::FileTooLarge { max, .. }) if max == max_file_bytes
+        ));
+
+        // Nothing was evicted, in memory or on disk
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), on_disk);
+        assert_eq!(capped.all_records_including_expired().count(), 3);
+        assert_eq!(capped.check_permission(&requests[0]), Some(true));
+    }
+
+    #[test]
+    fn test_compact_collapses_redundant_records() {
+        for format in [PermissionStoreFormat::Json, PermissionStoreFormat::Log] {
+            let dir = TempDir::new().unwrap();
+            let mut store = store_in(&dir, format);
+            let now = Utc::now().timestamp();
+
+            let mixed = tool_request("developer__shell", json!({"command": "ls"}));
+            let expired = tool_request("developer__shell", json!({"command": "pwd"}));
+            let single = tool_request("developer__shell", json!({"command": "rm"}));
+            let seed = [
+                (&mixed, true, now - 400, Some(now - 350)),
+                (&mixed, true, now - 300, None),
+                (&mixed, false, now - 200, None),
+                (&mixed, true, now - 100, None),
+                (&expired, true, now - 300, Some(now - 200)),
+                (&expired, true, now - 100, Some(now - 20)),
+                (&single, false, now - 100, None),
+            ];
+            for (request, allowed, timestamp, expiry) in seed {
+                let op = match record_op(&store, request, allowed) {
+                    PermissionLogOp::Record { key, mut record } => {
+                        record.timestamp = timestamp;
+                        record.expiry = expiry;
+                        // Push directly to bypass merging of repeated decisions
+                        store
+                            .permissions
+                            .entry(key.clone())
+                            .or_default()
+                            .push(record.clone());
+                        PermissionLogOp::Record { key, record }
+                    }
+                    _ => unreachable!(),
+                };
+                if format == PermissionStoreFormat::Log {
+                    store.append_log_op(&op).unwrap();
+                }
+            }
+            assert_eq!(store.check_permission(&mixed), Some(true));
+
+            assert_eq!(store.compact().unwrap(), 4);
+            assert_eq!(store.all_records_including_expired().count(), 3);
+            assert_eq!(store.check_permission(&mixed), Some(true));
+            assert_eq!(store.check_permission(&expired), None);
+            assert_eq!(store.check_permission(&single), Some(false));
+
+            let mixed_key = format!(
+                "developer__shell:{}",
+                store.hash_tool_context(&mixed).unwrap()
+            );
+            let kept: Vec<bool> = store.permissions[&mixed_key]
+                .iter()
+                .map(|record| record.allowed)
+                .collect();
+            assert_eq!(kept, vec![false, true]);
+            assert_eq!(store.permissions[&mixed_key][1].approval_count, 2);
+
+            let reloaded = store_in(&dir, format);
+            assert_eq!(reloaded.all_records_including_expired().count(), 3);
+            assert_eq!(reloaded.check_permission(&mixed), Some(true));
+        }
+    }
+
+    #[test]
+    fn test_compact_keeps_lasting_decisions() {
+        for format in [PermissionStoreFormat::Json, PermissionStoreFormat::Log] {
+            let dir = TempDir::new().unwrap();
+            let mut store = store_in(&dir, format);
+            let ls = tool_request("developer__shell", json!({"command": "ls"}));
+            store
+                .record_permission(&ls, true, PermissionDuration::Forever)
+                .unwrap();
+            store
+                .record_permission(&ls, true, PermissionDuration::Session)
+                .unwrap();
+
+            // The session grant doesn't outlast the lasting one, so both stay
+            assert_eq!(store.compact().unwrap(), 0);
+            assert_eq!(store.all_records_including_expired().count(), 2);
+
+            // The lasting grant still applies once the session is over
+            let mut reloaded = store_in(&dir, format);
+            assert_eq!(reloaded.check_permission(&ls), Some(true));
+            assert_eq!(reloaded.compact().unwrap(), 1);
+            assert_eq!(reloaded.check_permission(&ls), Some(true));
+            assert_eq!(store_in(&dir, format).check_permission(&ls), Some(true));
+        }
+    }
+
+    #[test]
+    fn test_allowlist_round_trip() {
+        let source_dir = TempDir::new().unwrap();
+        let mut source = store_in(&source_dir, PermissionStoreFormat::Json);
+
+        let view = tool_request("developer__text_editor", json!({"command": "view"}));
+        let list = tool_request("developer__shell", json!({"command": "ls"}));
+        let remove = tool_request("developer__shell", json!({"command": "rm -rf /"}));
+        let revoked = tool_request("developer__shell", json!({"command": "curl"}));
+        source.record_permission(&view, true, None).unwrap();
+        source
+            .record_permission(&list, true, Some(Duration::from_secs(3600)))
+            .unwrap();
+        source.record_permission(&remove, false, None).unwrap();
+        source.record_permission(&revoked, true, None).unwrap();
+        source.record_permission(&revoked, false, None).unwrap();
+
+        let exported = source.export_allowlist().unwrap();
+        assert_eq!(exported, source.export_allowlist().unwrap());
+        assert!(!exported.contains("rm -rf"));
+
+        for format in [PermissionStoreFormat::Json, PermissionStoreFormat::Log] {
+            let dir = TempDir::new().unwrap();
+            let mut target = store_in(&dir, format);
+            let imported = target
+                .import_allowlist(&exported, Some(Duration::from_secs(600)))
+                .unwrap();
+            assert_eq!(imported, 2);
+
+            let reloaded = store_in(&dir, format);
+            assert_eq!(reloaded.check_permission(&view), Some(true));
+            assert_eq!(reloaded.check_permission(&list), Some(true));
+            assert_eq!(reloaded.check_permission(&remove), None);
+            assert_eq!(reloaded.check_permission(&revoked), None);
+            assert!(reloaded.expiring_within(&view, Duration::from_secs(600)));
+        }
+    }
+
+    #[test]
+    fn test_load_with_app_strategy() {
+        let home = TempDir::new().unwrap();
+        let home_path = home.path().to_string_lossy().to_string();
+        let config_home = home.path().join(".config").to_string_lossy().to_string();
+        let args = AppStrategyArgs {
+            top_level_domain: "Example".to_string(),
+            author: "Example".to_string(),
+            app_name: "white-label".to_string(),
+        };
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home_path.as_str())),
+                ("XDG_CONFIG_HOME", Some(config_home.as_str())),
+            ],
+            || {
+                let mut store = ToolPermissionStore::load_with_app_strategy(
+                    args.clone(),
+                    PermissionStoreFormat::Json,
+                )
+                .unwrap();
+                assert!(store.permissions_dir.starts_with(home.path()));
+                assert!(store
+                    .permissions_dir
+                    .to_string_lossy()
+                    .contains("white-label"));
+
+                let request = tool_request("developer__shell", json!({"command": "ls"}));
+                store.record_permission(&request, true, None).unwrap();
+                assert!(store.permissions_dir.join(PERMISSIONS_FILE).exists());
+
+                let reloaded =
+                    ToolPermissionStore::load_with_app_strategy(args, PermissionStoreFormat::Json)
+                        .unwrap();
+                assert_eq!(reloaded.check_permission(&request), Some(true));
+            },
+        );
+    }
+
+    #[test]
+    fn test_tool_wide_permissions() {
+        let dir = TempDir::new().unwrap();
+        let mut store = store_in(&dir, PermissionStoreFormat::Json);
+        let ls = tool_request("developer__shell", json!({"command": "ls"}));
+        let rm = tool_request("developer__shell", json!({"command": "rm -rf /"}));
+        let other_tool = tool_request("developer__text_editor", json!({"path": "a.txt"}));
+
+        store
+            .record_tool_wide_permission("developer__shell", true, None)
+            .unwrap();
+        assert_eq!(store.check_permission(&ls), Some(true));
+        assert_eq!(store.check_permission(&rm), Some(true));
+        assert_eq!(store.check_permission(&other_tool), None);
+
+        // A context-specific deny overrides the tool-wide allow for that call only
+        store.record_permission(&rm, false, None).unwrap();
+        assert_eq!(store.check_permission(&rm), Some(false));
+        assert_eq!(store.check_permission(&ls), Some(true));
+
+        let reloaded = store_in(&dir, PermissionStoreFormat::Json);
+        assert_eq!(reloaded.check_permission(&ls), Some(true));
+        assert_eq!(reloaded.check_permission(&rm), Some(false));
+        let scopes: HashSet<PermissionScope> = reloaded
+            .all_records_including_expired()
+            .map(|(_, record)| record.scope())
+            .collect();
+        assert_eq!(
+            scopes,
+            HashSet::from([PermissionScope::ToolWide, PermissionScope::ContextSpecific])
+        );
+
+        // Records written before scopes existed are context-specific
+        let legacy: ToolPermissionRecord = serde_json::from_value(json!({
+            "tool_name": "developer__shell",
+            "allowed": true,
+            "context_hash": "abc",
+            "timestamp": 1735689600,
+        }))
+        .unwrap();
+        assert_eq!(legacy.scope(), PermissionScope::ContextSpecific);
+    }
+
+    #[test]
+    fn test_permission_durations() {
+        let dir = TempDir::new().unwrap();
+        let mut store = store_in(&dir, PermissionStoreFormat::Json);
+        let ls = tool_request("developer__shell", json!({"command": "ls"}));
+        let cat = tool_request("developer__shell", json!({"command": "cat a.txt"}));
+        let pwd = tool_request("developer__shell", json!({"command": "pwd"}));
+
+        // Once grants the current call without recording anything
+        store
+            .record_permission(&ls, true, PermissionDuration::Once)
+            .unwrap();
+        assert_eq!(store.check_permission(&ls), None);
+        assert!(!dir.path().join(PERMISSIONS_FILE).exists());
+
+        store
+            .record_permission(&ls, true, PermissionDuration::Session)
+            .unwrap();
+        store
+            .record_permission(&cat, true, PermissionDuration::Forever)
+            .unwrap();
+        store
+            .record_permission(&pwd, true, PermissionDuration::For(Duration::from_secs(60)))
+            .unwrap();
+        assert_eq!(store.check_permission(&ls), Some(true));
+        assert_eq!(store.check_permission(&cat), Some(true));
+        assert_eq!(store.check_permission(&pwd), Some(true));
+        assert!(store.expiring_within(&pwd, Duration::from_secs(60)));
+
+        // A new store is a new session, so only the session grant is ignored
+        let reloaded = store_in(&dir, PermissionStoreFormat::Json);
+        assert_ne!(reloaded.session_id(), store.session_id());
+        assert_eq!(reloaded.check_permission(&ls), None);
+        assert_eq!(reloaded.check_permission_ignoring_expiry(&ls), None);
+        assert_eq!(reloaded.check_permission(&cat), Some(true));
+        assert_eq!(reloaded.check_permission(&pwd), Some(true));
+    }
+
+    #[test]
+    fn test_session_grants_stay_in_their_session() {
+        let dir = TempDir::new().unwrap();
+        let mut store = store_in(&dir, PermissionStoreFormat::Json);
+        let ls = tool_request("developer__shell", json!({"command": "ls"}));
+        let cat = tool_request("developer__shell", json!({"command": "cat a.txt"}));
+        let exported_tools = |store: &ToolPermissionStore| -> Vec<String> {
+            let entries: Vec<AllowlistEntry> =
+                serde_json::from_str(&store.export_allowlist().unwrap()).unwrap();
+            entries
+                .into_iter()
+                .map(|entry| entry.readable_context.unwrap())
+                .collect()
+        };
+
+        store
+            .record_permission(&ls, true, PermissionDuration::Session)
+            .unwrap();
+        store
+            .record_permission(&cat, true, PermissionDuration::Forever)
+            .unwrap();
+        // Session grants are never exported, even from their own session
+        assert_eq!(store.list_permissions().len(), 2);
+        assert_eq!(exported_tools(&store), vec![cat.to_readable_string()]);
+
+        // Loading again starts a new session, which ignores the grant but
+        // leaves it on disk for the session that made it
+        let mut reloaded = store_in(&dir, PermissionStoreFormat::Json);
+        assert_eq!(reloaded.check_permission(&ls), None);
+        assert_eq!(reloaded.check_permission_ignoring_expiry(&ls), None);
+        assert_eq!(reloaded.list_permissions().len(), 1);
+        assert_eq!(reloaded.approval_report().len(), 1);
+        assert_eq!(exported_tools(&reloaded), vec![cat.to_readable_string()]);
+        reloaded.cleanup_expired().unwrap();
+        assert_eq!(reloaded.all_records_including_expired().count(), 2);
+        let path = dir.path().join(PERMISSIONS_FILE);
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .contains(store.session_id()));
+
+        // A tool-wide decision applies in its place, expired or not
+        reloaded
+            .record_tool_wide_permission("developer__shell", false, Some(Duration::ZERO))
+            .unwrap();
+        assert_eq!(reloaded.check_permission(&ls), None);
+        assert_eq!(reloaded.check_permission_ignoring_expiry(&ls), Some(false));
+
+        // Compacting prunes the other session's grant along with expired records
+        assert_eq!(reloaded.compact().unwrap(), 2);
+        assert_eq!(reloaded.all_records_including_expired().count(), 1);
+        assert_eq!(reloaded.check_permission_ignoring_expiry(&ls), None);
+        assert!(!std::fs::read_to_string(&path)
+            .unwrap()
+            .contains(store.session_id()));
+    }
+
+    #[test]
+    fn test_revoke_and_list_permissions() {
+        for format in [PermissionStoreFormat::Json, PermissionStoreFormat::Log] {
+            let dir = TempDir::new().unwrap();
+            let mut store = store_in(&dir, format);
+            let ls = tool_request("developer__shell", json!({"command": "ls"}));
+            let pwd = tool_request("developer__shell", json!({"command": "pwd"}));
+            let edit = tool_request("developer__text_editor", json!({"path": "a.txt"}));
+            let fetch = tool_request("computercontroller__web_fetch", json!({"url": "x"}));
+
+            store.record_permission(&ls, true, None).unwrap();
+            store.record_permission(&pwd, true, None).unwrap();
+            store.record_permission(&edit, false, None).unwrap();
+            store
+                .record_tool_wide_permission("developer__shell", true, None)
+                .unwrap();
+            // Expired records are hidden before cleanup runs
+            store
+                .record_permission(&fetch, true, Some(Duration::from_secs(60)))
+                .unwrap();
+            let now = Utc::now().timestamp();
+            store = store.with_clock(move || now + 120);
+
+            let listed: Vec<(&str, Option<&str>)> = store
+                .list_permissions()
+                .into_iter()
+                .map(|record| (record.tool_name(), record.readable_context()))
+                .collect();
+            assert_eq!(listed.len(), 4);
+            assert_eq!(listed[0].0, "developer__shell");
+            assert_eq!(listed[3].0, "developer__text_editor");
+            assert!(listed[3].1.is_some());
+
+            assert!(store.revoke_context(&ls).unwrap());
+            assert!(!store.revoke_context(&ls).unwrap());
+            // The tool-wide allow still covers the revoked context
+            assert_eq!(store.check_permission(&ls), Some(true));
+            assert_eq!(store.list_permissions().len(), 3);
+
+            assert_eq!(store.revoke("developer__shell").unwrap(), 2);
+            assert_eq!(store.check_permission(&ls), None);
+            assert_eq!(store.check_permission(&pwd), None);
+            assert_eq!(store.check_permission(&edit), Some(false));
+
+            let reloaded = store_in(&dir, format);
+            assert_eq!(reloaded.check_permission(&pwd), None);
+            assert_eq!(reloaded.check_permission(&edit), Some(false));
+        }
+    }
+
+    #[test]
+    fn test_memory_store_skips_disk() {
+        let dir = TempDir::new().unwrap();
+        let mut store = store_in(&dir, PermissionStoreFormat::Memory);
+        let ls = tool_request("developer__shell", json!({"command": "ls"}));
+
+        store.record_permission(&ls, true, None).unwrap();
+        assert_eq!(store.check_permission(&ls), Some(true));
+        store.save().unwrap();
+        assert_eq!(store.compact().unwrap(), 0);
+
+        assert_eq!(store.revoke("developer__shell").unwrap(), 1);
+        assert_eq!(store.check_permission(&ls), None);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_with_dir_and_load_from() {
+        let dir = TempDir::new().unwrap();
+        let profile_dir = dir.path().join("profiles").join("work");
+        let request = tool_request("developer__shell", json!({"command": "ls"}));
+
+        // A directory that doesn't exist yet is an empty store
+        let empty = ToolPermissionStore::load_from(&profile_dir).unwrap();
+        assert!(empty.list_permissions().is_empty());
+        assert!(!profile_dir.exists());
+
+        let mut store = ToolPermissionStore::with_dir(&profile_dir);
+        store.record_permission(&request, true, None).unwrap();
+        assert!(profile_dir.join(PERMISSIONS_FILE).exists());
+
+        let reloaded = ToolPermissionStore::load_from(&profile_dir).unwrap();
+        assert_eq!(reloaded.check_permission(&request), Some(true));
+
+        // Other directories keep separate permissions
+        let other =
+            ToolPermissionStore::load_from(dir.path().join("profiles").join("home")).unwrap();
+        assert_eq!(other.check_permission(&request), None);
+    }
+
+    #[test]
+    fn test_records_per_key_are_capped() {
+        let dir = TempDir::new().unwrap();
+        let mut store = store_in(&dir, PermissionStoreFormat::Json);
+        let request = tool_request("developer__shell", json!({"command": "ls"}));
+        let key = format!(
+            "developer__shell:{}",
+            store.hash_tool_context(&request).unwrap()
+        );
+
+        // Alternate decisions so repeats can't be merged into one record
+        for i in 0..200 {
+            store.record_permission(&request, i % 2 == 0, None).unwrap();
+        }
+        assert_eq!(store.permissions[&key].len(), DEFAULT_MAX_RECORDS_PER_KEY);
+        assert_eq!(store.check_permission(&request), Some(false));
+
+        let reloaded = store_in(&dir, PermissionStoreFormat::Json);
+        assert_eq!(
+            reloaded.permissions[&key].len(),
+            DEFAULT_MAX_RECORDS_PER_KEY
+        );
+        assert_eq!(reloaded.check_permission(&request), Some(false));
+
+        let mut small = store_in(&dir, PermissionStoreFormat::Log).with_max_records_per_key(3);
+        for i in 0..10 {
+            small.record_permission(&request, i % 2 == 1, None).unwrap();
+        }
+        assert_eq!(small.permissions[&key].len(), 3);
+        assert_eq!(small.check_permission(&request), Some(true));
+    }
 }